@@ -1,28 +1,77 @@
-#[derive(Debug, PartialEq)]
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead};
+use std::num::ParseIntError;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Direction {
     Left,
     Right,
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct Rotation {
     pub direction: Direction,
-    pub distance: u32,
+    pub distance: u64,
+}
+
+/// Why a line could not be parsed as a rotation.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RotationParseError {
+    Empty,
+    BadDirection(char),
+    MissingDistance,
+    BadDistance {
+        text: String,
+        source: ParseIntError,
+    },
+    /// Unexpected characters after the distance, starting at byte `at`.
+    TrailingGarbage {
+        at: usize,
+    },
+}
+
+impl fmt::Display for RotationParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RotationParseError::Empty => write!(f, "rotation is empty"),
+            RotationParseError::BadDirection(c) => {
+                write!(f, "invalid direction {:?}, expected 'L' or 'R'", c)
+            }
+            RotationParseError::MissingDistance => write!(f, "missing distance"),
+            RotationParseError::BadDistance { text, source } => {
+                write!(f, "invalid distance {:?}: {}", text, source)
+            }
+            RotationParseError::TrailingGarbage { at } => {
+                write!(f, "unexpected characters after the distance at byte {}", at)
+            }
+        }
+    }
 }
 
-pub fn parse_rotation(input: &str) -> Result<Rotation, String> {
-    if input.is_empty() {
-        return Err("Input cannot be empty".to_string());
+impl Error for RotationParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RotationParseError::BadDistance { source, .. } => Some(source),
+            _ => None,
+        }
     }
+}
 
-    let direction = match input.chars().next().unwrap() {
-        'L' => Direction::Left,
-        'R' => Direction::Right,
-        c => return Err(format!("Invalid direction: {}", c)),
+pub fn parse_rotation(input: &str) -> Result<Rotation, RotationParseError> {
+    let mut chars = input.chars();
+    let direction = match chars.next() {
+        None => return Err(RotationParseError::Empty),
+        Some('L') => Direction::Left,
+        Some('R') => Direction::Right,
+        Some(c) => return Err(RotationParseError::BadDirection(c)),
     };
 
-    let distance = input[1..]
-        .parse::<u32>()
-        .map_err(|e| format!("Invalid distance: {}", e))?;
+    let distance_text = chars.as_str();
+    if distance_text.is_empty() {
+        return Err(RotationParseError::MissingDistance);
+    }
+    let distance = parse_count(input, distance_text)?;
 
     Ok(Rotation {
         direction,
@@ -30,6 +79,56 @@ pub fn parse_rotation(input: &str) -> Result<Rotation, String> {
     })
 }
 
+/// One line of a rotation program.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction {
+    Rotate(Rotation),
+    /// `U` or `U<n>`: take back the last `n` rotations still in effect.
+    Undo(usize),
+}
+
+/// Parses a rotation (`L68`, `R48`) or an undo (`U`, `U3`).
+pub fn parse_instruction(input: &str) -> Result<Instruction, RotationParseError> {
+    match input.strip_prefix('U') {
+        Some("") => Ok(Instruction::Undo(1)),
+        Some(count_text) => {
+            let count = parse_count(input, count_text)?;
+            Ok(Instruction::Undo(
+                usize::try_from(count).unwrap_or(usize::MAX),
+            ))
+        }
+        None => parse_rotation(input).map(Instruction::Rotate),
+    }
+}
+
+/// Parses the number that ends `input`, starting at `text`.
+fn parse_count(input: &str, text: &str) -> Result<u64, RotationParseError> {
+    let digits_end = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    if digits_end > 0 && digits_end < text.len() {
+        return Err(RotationParseError::TrailingGarbage {
+            at: input.len() - text.len() + digits_end,
+        });
+    }
+
+    // `str::parse` accepts a leading `+`, so a count not starting with a digit
+    // is parsed from that first character alone, which always fails
+    let number = match text.chars().next() {
+        Some(c) if !c.is_ascii_digit() => &text[..c.len_utf8()],
+        _ => text,
+    };
+    number
+        .parse::<u64>()
+        .map_err(|source| RotationParseError::BadDistance {
+            text: text.to_string(),
+            source,
+        })
+}
+
+/// Number of positions on the dial (0 through 99).
+pub const DIAL_SIZE: u32 = 100;
+
 pub struct Safe {
     pub position: u32,
 }
@@ -45,7 +144,27 @@ impl Safe {
         Self::default()
     }
 
-    pub fn rotate(&mut self, direction: Direction, distance: u32) -> u32 {
+    /// Creates a safe whose dial starts at `position`.
+    ///
+    /// # Panics
+    /// Panics if `position` is not on the dial (`0..DIAL_SIZE`).
+    pub fn starting_at(position: u32) -> Self {
+        assert!(
+            position < DIAL_SIZE,
+            "start position {} is off the dial (0..{})",
+            position,
+            DIAL_SIZE
+        );
+        Self { position }
+    }
+
+    /// Rotates the dial and returns how many times it pointed at 0 along the way.
+    /// Full turns are split off before adding to the position, so any `u64`
+    /// distance is handled without overflow.
+    pub fn rotate(&mut self, direction: Direction, distance: u64) -> u64 {
+        let full_turns = distance / 100;
+        let partial = (distance % 100) as u32;
+
         match direction {
             Direction::Left => {
                 let dist_to_first = if self.position == 0 {
@@ -54,63 +173,528 @@ impl Safe {
                     self.position
                 };
 
-                self.position = (self.position + 100 - (distance % 100)) % 100;
-
-                if distance < dist_to_first {
-                    0
-                } else {
-                    1 + (distance - dist_to_first) / 100
-                }
+                let partial_crossing = u64::from(partial >= dist_to_first);
+                self.position = (self.position + 100 - partial) % 100;
+                full_turns + partial_crossing
             }
             Direction::Right => {
-                let count = (self.position + distance) / 100;
-                self.position = (self.position + distance) % 100;
-                count
+                let partial_crossing = u64::from(self.position + partial >= 100);
+                self.position = (self.position + partial) % 100;
+                full_turns + partial_crossing
             }
         }
     }
+
+    /// Applies `rotations` in order and tallies how often each position was
+    /// clicked onto (the starting position is not counted). Each rotation adds
+    /// its full turns to every bucket and its partial arc to a difference
+    /// array, so the cost does not depend on the distances.
+    pub fn apply_batch(&mut self, rotations: &[Rotation]) -> BatchResult {
+        let mut full_turns = 0;
+        let mut arc_starts = [0i64; DIAL_SIZE as usize + 1];
+        let mut crossings = 0;
+
+        for rotation in rotations {
+            let from = self.position;
+            full_turns += rotation.distance / u64::from(DIAL_SIZE);
+            let partial = (rotation.distance % u64::from(DIAL_SIZE)) as u32;
+            let first = match rotation.direction {
+                Direction::Left => (from + DIAL_SIZE - partial) % DIAL_SIZE,
+                Direction::Right => (from + 1) % DIAL_SIZE,
+            };
+            add_arc(&mut arc_starts, first, partial);
+            crossings += self.rotate(rotation.direction, rotation.distance);
+        }
+
+        let mut visit_counts = [full_turns; DIAL_SIZE as usize];
+        let mut arcs_covering = 0;
+        for (count, delta) in visit_counts.iter_mut().zip(arc_starts) {
+            arcs_covering += delta;
+            *count += arcs_covering as u64;
+        }
+
+        BatchResult {
+            crossings,
+            final_position: self.position,
+            visit_counts,
+        }
+    }
+}
+
+/// Records a circular arc of `length` positions starting at `first`.
+fn add_arc(arc_starts: &mut [i64], first: u32, length: u32) {
+    let (first, end) = (first as usize, (first + length) as usize);
+    let dial = DIAL_SIZE as usize;
+    arc_starts[first] += 1;
+    if end <= dial {
+        arc_starts[end] -= 1;
+    } else {
+        arc_starts[dial] -= 1;
+        arc_starts[0] += 1;
+        arc_starts[end - dial] -= 1;
+    }
+}
+
+/// Outcome of [`Safe::apply_batch`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchResult {
+    pub crossings: u64,
+    pub final_position: u32,
+    /// How many clicks landed on each position.
+    pub visit_counts: [u64; DIAL_SIZE as usize],
 }
 
 pub fn solve(input: &str) -> u32 {
-    let mut safe = Safe::default();
-    let mut zero_count = 0;
+    let mut zero_landings = 0;
+    simulate(input, |event| {
+        if let SimEvent::Rotated { to: 0, .. } = event {
+            zero_landings += 1;
+        }
+    })
+    // We unwrap here because the input is guaranteed to be valid in the puzzle
+    .unwrap();
+    zero_landings
+}
 
-    parse_and_iterate(input, |rotation| {
-        safe.rotate(rotation.direction, rotation.distance);
-        if safe.position == 0 {
-            zero_count += 1;
+pub fn solve_part2(input: &str) -> u64 {
+    let mut total_crossings = 0;
+    simulate(input, |event| {
+        if let SimEvent::Rotated { crossings, .. } = event {
+            total_crossings += crossings;
         }
-    });
+    })
+    // We unwrap here because the input is guaranteed to be valid in the puzzle
+    .unwrap();
+    total_crossings
+}
 
-    zero_count
+/// Something that happened while [`simulate`] ran a rotation program.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SimEvent {
+    /// A rotation line was read; it is applied right after.
+    Parsed(Rotation),
+    /// The dial turned from `from` to `to`, pointing at 0 `crossings` times.
+    Rotated { from: u32, to: u32, crossings: u64 },
+    /// A `U` line turned the dial back from `from` to `to`.
+    Undone { from: u32, to: u32 },
+    /// Every instruction was applied.
+    Finished { final_position: u32 },
 }
 
-pub fn solve_part2(input: &str) -> u32 {
-    let mut safe = Safe::default();
-    let mut total_crossings = 0;
+/// Why [`simulate`] stopped before the end of the program.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    /// A line is not a valid instruction.
+    Line {
+        line: usize,
+        source: RotationParseError,
+    },
+    /// A `U` line asked to take back more rotations than were in effect.
+    Undo {
+        line: usize,
+        requested: usize,
+        available: usize,
+    },
+}
 
-    parse_and_iterate(input, |rotation| {
-        total_crossings += safe.rotate(rotation.direction, rotation.distance);
-    });
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Line { line, source } => write!(f, "line {}: {}", line, source),
+            ParseError::Undo {
+                line,
+                requested,
+                available,
+            } => write!(
+                f,
+                "line {}: cannot undo {} rotation(s), only {} to undo",
+                line, requested, available
+            ),
+        }
+    }
+}
 
-    total_crossings
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ParseError::Line { source, .. } => Some(source),
+            ParseError::Undo { .. } => None,
+        }
+    }
+}
+
+/// Runs the program from the default start, reporting each event to
+/// `observer` as it happens. Lines are parsed lazily, so events for the lines
+/// before a malformed one are still reported, but nothing after it.
+pub fn simulate<F: FnMut(&SimEvent)>(input: &str, mut observer: F) -> Result<(), ParseError> {
+    let mut final_position = Safe::default().position;
+    for step in Steps::new(Safe::default(), parse_lines(input)) {
+        let step = step?;
+        let (from, to) = (step.position_before, step.position_after);
+        if step.undo {
+            observer(&SimEvent::Undone { from, to });
+        } else {
+            observer(&SimEvent::Parsed(step.rotation));
+            observer(&SimEvent::Rotated {
+                from,
+                to,
+                crossings: step.crossings,
+            });
+        }
+        final_position = to;
+    }
+    observer(&SimEvent::Finished { final_position });
+    Ok(())
+}
+
+/// Like [`solve`], but with the dial starting at `start` instead of 50.
+///
+/// # Panics
+/// Panics if `start` is not on the dial.
+pub fn solve_with_start(input: &str, start: u32) -> u32 {
+    final_zero_landings(program_steps(Safe::starting_at(start), input))
+}
+
+/// Like [`solve_part2`], but with the dial starting at `start` instead of 50.
+///
+/// # Panics
+/// Panics if `start` is not on the dial.
+pub fn solve_part2_with_start(input: &str, start: u32) -> u64 {
+    final_crossings(program_steps(Safe::starting_at(start), input))
+}
+
+/// Finds the start position with the fewest Part 1 zero landings, returning
+/// `(start, landings)`. Ties go to the lowest start.
+pub fn best_start(input: &str) -> (u32, u32) {
+    // We unwrap here because the input is guaranteed to be valid in the puzzle
+    let instructions: Vec<_> = parse_lines(input).map(Result::unwrap).collect();
+    (0..DIAL_SIZE)
+        .map(|start| {
+            let instructions = instructions.iter().cloned().map(Ok);
+            let steps = Steps::new(Safe::starting_at(start), instructions);
+            (start, final_zero_landings(steps.map(Result::unwrap)))
+        })
+        .min_by_key(|&(start, landings)| (landings, start))
+        .expect("the dial has at least one position")
+}
+
+/// 1-based instruction after which the dial first rests back on its starting
+/// position. Passing through it mid-rotation does not count, nor does an undo
+/// of several rotations stopping there before it is complete.
+pub fn first_return_to_start(input: &str) -> Option<usize> {
+    returns_to_start(input).next()
+}
+
+/// Number of instructions after which the dial rests on its starting position.
+pub fn count_returns_to_start(input: &str) -> u32 {
+    returns_to_start(input).count() as u32
+}
+
+fn returns_to_start(input: &str) -> impl Iterator<Item = usize> + '_ {
+    let start = Safe::default().position;
+    // We unwrap here because the input is guaranteed to be valid in the puzzle
+    let mut steps = Steps::new(Safe::default(), parse_lines(input));
+    std::iter::from_fn(move || {
+        loop {
+            let step = steps.next()?.unwrap();
+            // An undo of n rotations is one instruction, done after its last step
+            if steps.pending_undos == 0 && step.position_after == start {
+                return Some(steps.instructions_read);
+            }
+        }
+    })
+}
+
+/// Streaming version of [`solve`]: reads rotations line by line without
+/// loading the whole input. Malformed lines are reported as `InvalidData`.
+pub fn solve_from_reader<R: BufRead>(reader: R) -> io::Result<u32> {
+    with_reader_steps(reader, |steps| final_zero_landings(steps))
 }
 
-fn parse_and_iterate(input: &str, mut processor: impl FnMut(Rotation)) {
-    for line in input.lines() {
-        if line.trim().is_empty() {
+/// Streaming version of [`solve_part2`].
+pub fn solve_part2_from_reader<R: BufRead>(reader: R) -> io::Result<u64> {
+    with_reader_steps(reader, |steps| final_crossings(steps))
+}
+
+/// What happened to the dial when a single rotation was applied or undone.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepTrace {
+    /// The rotation applied, or the one being taken back when `undo` is set.
+    pub rotation: Rotation,
+    /// Set when this step reverses an earlier rotation because of a `U` line.
+    pub undo: bool,
+    pub position_before: u32,
+    pub position_after: u32,
+    /// Times the dial pointed at 0 during this rotation (Part 2 metric).
+    /// Always 0 for undo steps.
+    pub crossings: u64,
+    /// Rotations so far that ended on 0 (Part 1 answer up to this step).
+    pub total_zero_landings: u32,
+    /// Crossings so far (Part 2 answer up to this step).
+    pub total_crossings: u64,
+}
+
+/// Applies every instruction of the input in order and records each step.
+/// An undo of `n` rotations contributes `n` steps.
+pub fn trace(input: &str) -> Result<Vec<StepTrace>, String> {
+    Steps::new(Safe::default(), parse_lines(input))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+/// Aggregate figures over a whole rotation program, gathered in one pass.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RotationStats {
+    pub total_left_distance: u64,
+    pub total_right_distance: u64,
+    /// Lowest position the dial pointed at, including mid-rotation clicks.
+    pub min_position: u32,
+    /// Highest position the dial pointed at, including mid-rotation clicks.
+    pub max_position: u32,
+    /// Distinct positions the dial pointed at, the start included.
+    pub distinct_positions: usize,
+    /// Most consecutive rotations in the same direction.
+    pub longest_direction_run: usize,
+    /// Part 1 answer.
+    pub zero_landings: u32,
+    /// Part 2 answer.
+    pub zero_crossings: u64,
+}
+
+/// Simulates the input once and gathers [`RotationStats`] along the way.
+/// Undone rotations are taken back without counting as movement.
+pub fn analyze(input: &str) -> Result<RotationStats, String> {
+    let start = Safe::default().position;
+
+    let mut visited = [false; DIAL_SIZE as usize];
+    visited[start as usize] = true;
+    let mut stats = RotationStats {
+        total_left_distance: 0,
+        total_right_distance: 0,
+        min_position: start,
+        max_position: start,
+        distinct_positions: 0,
+        longest_direction_run: 0,
+        zero_landings: 0,
+        zero_crossings: 0,
+    };
+    let mut run = (None, 0);
+
+    for step in trace(input)? {
+        stats.zero_landings = step.total_zero_landings;
+        stats.zero_crossings = step.total_crossings;
+        if step.undo {
             continue;
         }
 
-        // We unwrap here because the input is guaranteed to be valid in the puzzle
-        let rotation = parse_rotation(line.trim()).unwrap();
-        processor(rotation);
+        let Rotation {
+            direction,
+            distance,
+        } = step.rotation;
+        match direction {
+            Direction::Left => stats.total_left_distance += distance,
+            Direction::Right => stats.total_right_distance += distance,
+        }
+        run = match run {
+            (Some(previous), length) if previous == direction => (Some(direction), length + 1),
+            _ => (Some(direction), 1),
+        };
+        stats.longest_direction_run = stats.longest_direction_run.max(run.1);
+        mark_visited(&mut visited, step.position_before, direction, distance);
+    }
+
+    let visited_positions = || (0..DIAL_SIZE).filter(|&p| visited[p as usize]);
+    stats.min_position = visited_positions().min().unwrap_or(start);
+    stats.max_position = visited_positions().max().unwrap_or(start);
+    stats.distinct_positions = visited_positions().count();
+    Ok(stats)
+}
+
+/// Marks every position the dial clicks through. A rotation of a full turn
+/// or more visits the whole dial, so at most `DIAL_SIZE` clicks are walked.
+fn mark_visited(visited: &mut [bool], from: u32, direction: Direction, distance: u64) {
+    let clicks = distance.min(u64::from(DIAL_SIZE)) as u32;
+    for click in 1..=clicks {
+        let position = match direction {
+            Direction::Left => (from + DIAL_SIZE - click % DIAL_SIZE) % DIAL_SIZE,
+            Direction::Right => (from + click) % DIAL_SIZE,
+        };
+        visited[position as usize] = true;
+    }
+}
+
+/// Replays instructions on a safe, yielding one [`StepTrace`] per rotation
+/// applied or undone. Rotations still in effect are kept so `U` can pop them.
+struct Steps<I> {
+    instructions: I,
+    safe: Safe,
+    applied: Vec<Rotation>,
+    pending_undos: usize,
+    /// Instructions taken from `instructions` so far, the one being replayed included.
+    instructions_read: usize,
+    total_zero_landings: u32,
+    total_crossings: u64,
+}
+
+impl<I: Iterator<Item = Result<(usize, Instruction), ParseError>>> Steps<I> {
+    fn new(safe: Safe, instructions: I) -> Self {
+        Self {
+            instructions,
+            safe,
+            applied: Vec::new(),
+            pending_undos: 0,
+            instructions_read: 0,
+            total_zero_landings: 0,
+            total_crossings: 0,
+        }
+    }
+
+    fn apply(&mut self, rotation: Rotation) -> StepTrace {
+        let position_before = self.safe.position;
+        let crossings = self.safe.rotate(rotation.direction, rotation.distance);
+        if self.safe.position == 0 {
+            self.total_zero_landings += 1;
+        }
+        self.total_crossings += crossings;
+        self.applied.push(rotation.clone());
+        self.step(rotation, false, position_before, crossings)
+    }
+
+    /// Turns the dial back by the last applied rotation. The clicks it made
+    /// already happened, so totals are left untouched.
+    fn undo_last(&mut self) -> StepTrace {
+        let rotation = self
+            .applied
+            .pop()
+            .expect("undo count checked against history");
+        let position_before = self.safe.position;
+        let reverse = match rotation.direction {
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+        };
+        self.safe.rotate(reverse, rotation.distance);
+        self.step(rotation, true, position_before, 0)
+    }
+
+    fn step(
+        &self,
+        rotation: Rotation,
+        undo: bool,
+        position_before: u32,
+        crossings: u64,
+    ) -> StepTrace {
+        StepTrace {
+            rotation,
+            undo,
+            position_before,
+            position_after: self.safe.position,
+            crossings,
+            total_zero_landings: self.total_zero_landings,
+            total_crossings: self.total_crossings,
+        }
+    }
+}
+
+impl<I: Iterator<Item = Result<(usize, Instruction), ParseError>>> Iterator for Steps<I> {
+    type Item = Result<StepTrace, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending_undos == 0 {
+            let instruction = match self.instructions.next()? {
+                Ok(instruction) => instruction,
+                Err(e) => return Some(Err(e)),
+            };
+            self.instructions_read += 1;
+            match instruction {
+                (_, Instruction::Rotate(rotation)) => return Some(Ok(self.apply(rotation))),
+                (line, Instruction::Undo(requested)) => {
+                    if requested > self.applied.len() {
+                        return Some(Err(ParseError::Undo {
+                            line,
+                            requested,
+                            available: self.applied.len(),
+                        }));
+                    }
+                    self.pending_undos = requested;
+                }
+            }
+        }
+
+        self.pending_undos -= 1;
+        Some(Ok(self.undo_last()))
+    }
+}
+
+fn final_zero_landings(steps: impl Iterator<Item = StepTrace>) -> u32 {
+    steps.fold(0, |_, step| step.total_zero_landings)
+}
+
+fn final_crossings(steps: impl Iterator<Item = StepTrace>) -> u64 {
+    steps.fold(0, |_, step| step.total_crossings)
+}
+
+fn program_steps(safe: Safe, input: &str) -> impl Iterator<Item = StepTrace> + '_ {
+    // We unwrap here because the input is guaranteed to be valid in the puzzle
+    Steps::new(safe, parse_lines(input)).map(Result::unwrap)
+}
+
+/// Parses the non-blank lines of `input` with their 1-based line numbers.
+fn parse_lines(input: &str) -> impl Iterator<Item = Result<(usize, Instruction), ParseError>> + '_ {
+    input
+        .lines()
+        .enumerate()
+        .filter_map(|(index, line)| parse_line(index + 1, line).transpose())
+}
+
+/// Like [`parse_lines`], but stops quietly at the first I/O error and leaves
+/// it in `read_error`.
+fn read_instructions<'a, R: BufRead + 'a>(
+    reader: R,
+    read_error: &'a mut Option<io::Error>,
+) -> impl Iterator<Item = Result<(usize, Instruction), ParseError>> + 'a {
+    reader
+        .lines()
+        .map_while(|line| line.map_err(|e| *read_error = Some(e)).ok())
+        .enumerate()
+        .filter_map(|(index, line)| parse_line(index + 1, &line).transpose())
+}
+
+/// Parses the program line numbered `line`; blank lines hold no instruction.
+fn parse_line(line: usize, text: &str) -> Result<Option<(usize, Instruction)>, ParseError> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(None);
+    }
+    parse_instruction(text)
+        .map(|instruction| Some((line, instruction)))
+        .map_err(|source| ParseError::Line { line, source })
+}
+
+/// Feeds the steps replayed from `reader` to `consume`, stopping at the first
+/// I/O, parse or undo error and returning it instead of the result.
+fn with_reader_steps<R: BufRead, T>(
+    reader: R,
+    consume: impl FnOnce(&mut dyn Iterator<Item = StepTrace>) -> T,
+) -> io::Result<T> {
+    let mut read_error = None;
+    let mut step_error = None;
+    let result = {
+        let mut steps = Steps::new(Safe::default(), read_instructions(reader, &mut read_error))
+            .map_while(|step| step.map_err(|e| step_error = Some(e)).ok());
+        consume(&mut steps)
+    };
+
+    match (read_error, step_error) {
+        (Some(e), _) => Err(e),
+        (None, Some(e)) => Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        (None, None) => Ok(result),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::io::{BufReader, Cursor, Read};
 
     #[test]
     fn parse_left_rotation() {
@@ -205,4 +789,508 @@ mod tests {
         assert_eq!(solve(input), 1055);
         assert_eq!(solve_part2(input), 6386);
     }
+
+    #[test]
+    fn solve_from_reader_example() {
+        let input = "L68\nL30\nR48\nL5\nR60\nL55\nL1\nL99\nR14\nL82";
+        assert_eq!(solve_from_reader(Cursor::new(input)).unwrap(), 3);
+        assert_eq!(solve_part2_from_reader(Cursor::new(input)).unwrap(), 6);
+    }
+
+    /// Hands out at most three bytes per read, so lines straddle reads.
+    struct TinyChunks<'a>(&'a [u8]);
+
+    impl Read for TinyChunks<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min(3).min(self.0.len());
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn solve_from_reader_handles_lines_split_across_reads() {
+        let input = "L68\nL30\nR48\nL5\nR60\nL55\nL1\nL99\nR14\nL82\n";
+        let reader = BufReader::with_capacity(3, TinyChunks(input.as_bytes()));
+        assert_eq!(solve_from_reader(reader).unwrap(), 3);
+        let reader = BufReader::with_capacity(3, TinyChunks(input.as_bytes()));
+        assert_eq!(solve_part2_from_reader(reader).unwrap(), 6);
+    }
+
+    #[test]
+    fn solve_from_reader_reports_malformed_line_number() {
+        let input = "L68\n\nX30\nR48";
+        let error = solve_from_reader(Cursor::new(input)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("line 3"), "{}", error);
+    }
+
+    #[test]
+    fn parse_rotation_accepts_distances_beyond_u32() {
+        let rotation = parse_rotation("R10000000000").unwrap();
+        assert_eq!(rotation.distance, 10_000_000_000);
+    }
+
+    #[test]
+    fn rotate_right_huge_distance_counts_exact_crossings() {
+        let mut safe = Safe::default(); // 50
+        // 42949672 full turns, then 95 more clicks pass 0 once more
+        let crossings = safe.rotate(Direction::Right, 4294967295);
+        assert_eq!(crossings, 42949673);
+        assert_eq!(safe.position, 45);
+    }
+
+    #[test]
+    fn rotate_left_huge_distance_counts_exact_crossings() {
+        let mut safe = Safe::default(); // 50
+        let crossings = safe.rotate(Direction::Left, 4294967295);
+        assert_eq!(crossings, 42949673);
+        assert_eq!(safe.position, 55);
+    }
+
+    #[test]
+    fn rotate_max_distance_does_not_overflow() {
+        let mut safe = Safe { position: 99 };
+        let crossings = safe.rotate(Direction::Right, u64::MAX);
+        assert_eq!(crossings, u64::MAX / 100 + 1);
+        assert_eq!(safe.position, ((99 + u64::MAX % 100) % 100) as u32);
+    }
+
+    #[test]
+    fn widened_distance_keeps_example_answers() {
+        let input = "L68\nL30\nR48\nL5\nR60\nL55\nL1\nL99\nR14\nL82";
+        assert_eq!(solve(input), 3);
+        assert_eq!(solve_part2(input), 6);
+    }
+
+    #[test]
+    fn trace_example_records_every_step() {
+        let input = "L68\nL30\nR48\nL5\nR60\nL55\nL1\nL99\nR14\nL82";
+        let steps = trace(input).unwrap();
+        assert_eq!(steps.len(), 10);
+
+        let first = &steps[0];
+        assert_eq!(first.rotation.direction, Direction::Left);
+        assert_eq!(first.rotation.distance, 68);
+        assert_eq!((first.position_before, first.position_after), (50, 82));
+        assert_eq!(first.crossings, 1);
+
+        let last = steps.last().unwrap();
+        assert_eq!(last.position_after, 32);
+        assert_eq!(last.total_zero_landings, 3);
+        assert_eq!(last.total_crossings, 6);
+    }
+
+    #[test]
+    fn trace_of_empty_input_is_empty() {
+        assert_eq!(trace("").unwrap(), Vec::new());
+        assert_eq!(trace("\n\n").unwrap(), Vec::new());
+    }
+
+    #[test]
+    fn trace_reports_malformed_line() {
+        let error = trace("L68\nQ1").unwrap_err();
+        assert!(error.starts_with("line 2:"), "{}", error);
+    }
+
+    #[test]
+    fn solve_with_start_50_matches_solve() {
+        let input = "L68\nL30\nR48\nL5\nR60\nL55\nL1\nL99\nR14\nL82";
+        assert_eq!(solve_with_start(input, 50), solve(input));
+        assert_eq!(solve_part2_with_start(input, 50), solve_part2(input));
+    }
+
+    #[test]
+    fn solve_with_start_changes_landings() {
+        // From 10, L10 lands on 0 and R100 comes back to 0
+        let input = "L10\nR100";
+        assert_eq!(solve_with_start(input, 10), 2);
+        assert_eq!(solve_part2_with_start(input, 10), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "off the dial")]
+    fn solve_with_start_rejects_position_off_the_dial() {
+        solve_with_start("R1", DIAL_SIZE);
+    }
+
+    #[test]
+    fn best_start_breaks_ties_by_lowest_start() {
+        // A full turn brings every start back to itself: only start 0 lands on 0,
+        // so starts 1..=99 tie with no landings.
+        assert_eq!(best_start("R100"), (1, 0));
+        assert_eq!(best_start("R100"), best_start("R100"));
+    }
+
+    #[test]
+    fn best_start_finds_fewest_landings() {
+        let input = "L68\nL30\nR48\nL5\nR60\nL55\nL1\nL99\nR14\nL82";
+        let (start, landings) = best_start(input);
+        assert_eq!(landings, solve_with_start(input, start));
+        assert!((0..DIAL_SIZE).all(|other| solve_with_start(input, other) >= landings));
+    }
+
+    #[test]
+    fn parse_rotation_rejects_empty_input() {
+        assert_eq!(parse_rotation(""), Err(RotationParseError::Empty));
+    }
+
+    #[test]
+    fn parse_rotation_rejects_unknown_direction() {
+        assert_eq!(
+            parse_rotation("X5"),
+            Err(RotationParseError::BadDirection('X'))
+        );
+    }
+
+    #[test]
+    fn parse_rotation_rejects_missing_distance() {
+        assert_eq!(
+            parse_rotation("L"),
+            Err(RotationParseError::MissingDistance)
+        );
+    }
+
+    #[test]
+    fn parse_rotation_rejects_non_numeric_distance() {
+        let error = parse_rotation("LR5").unwrap_err();
+        match &error {
+            RotationParseError::BadDistance { text, .. } => assert_eq!(text, "R5"),
+            other => panic!("unexpected error {:?}", other),
+        }
+        assert!(error.source().is_some());
+
+        assert!(matches!(
+            parse_rotation("L+5"),
+            Err(RotationParseError::BadDistance { text, .. }) if text == "+5"
+        ));
+        assert!(matches!(
+            parse_instruction("U+3"),
+            Err(RotationParseError::BadDistance { text, .. }) if text == "+3"
+        ));
+    }
+
+    #[test]
+    fn parse_rotation_rejects_overflowing_distance() {
+        assert!(matches!(
+            parse_rotation("R99999999999999999999"),
+            Err(RotationParseError::BadDistance { .. })
+        ));
+    }
+
+    #[test]
+    fn parse_rotation_rejects_trailing_garbage() {
+        assert_eq!(
+            parse_rotation("L68x"),
+            Err(RotationParseError::TrailingGarbage { at: 3 })
+        );
+    }
+
+    #[test]
+    fn rotation_parse_error_messages_are_descriptive() {
+        assert_eq!(
+            RotationParseError::MissingDistance.to_string(),
+            "missing distance"
+        );
+        assert_eq!(
+            parse_rotation("LR5").unwrap_err().to_string(),
+            "invalid distance \"R5\": invalid digit found in string"
+        );
+    }
+
+    #[test]
+    fn analyze_example_stats() {
+        let input = "L68\nL30\nR48\nL5\nR60\nL55\nL1\nL99\nR14\nL82";
+        let stats = analyze(input).unwrap();
+        // Left: 68 + 30 + 5 + 55 + 1 + 99 + 82, right: 48 + 60 + 14
+        assert_eq!(stats.total_left_distance, 340);
+        assert_eq!(stats.total_right_distance, 122);
+        // L55, L1, L99
+        assert_eq!(stats.longest_direction_run, 3);
+        // L99 from 99 sweeps every position down to 0
+        assert_eq!(stats.distinct_positions, 100);
+        assert_eq!((stats.min_position, stats.max_position), (0, 99));
+        assert_eq!(stats.zero_landings, 3);
+        assert_eq!(stats.zero_crossings, 6);
+    }
+
+    #[test]
+    fn analyze_tracks_partial_arcs() {
+        // 50 -> 55, then back down through 50 to 45
+        let stats = analyze("R5\nL10").unwrap();
+        assert_eq!(stats.distinct_positions, 11);
+        assert_eq!((stats.min_position, stats.max_position), (45, 55));
+        assert_eq!(stats.longest_direction_run, 1);
+    }
+
+    #[test]
+    fn analyze_full_turn_visits_every_position() {
+        let stats = analyze("R100").unwrap();
+        assert_eq!(stats.distinct_positions, 100);
+        assert_eq!(stats.zero_landings, 0);
+        assert_eq!(stats.zero_crossings, 1);
+    }
+
+    #[test]
+    fn analyze_empty_input_only_visits_start() {
+        let stats = analyze("").unwrap();
+        assert_eq!(stats.distinct_positions, 1);
+        assert_eq!((stats.min_position, stats.max_position), (50, 50));
+        assert_eq!(stats.longest_direction_run, 0);
+    }
+
+    /// Click-by-click reference for `apply_batch`.
+    fn naive_batch(start: u32, rotations: &[Rotation]) -> BatchResult {
+        let mut position = start;
+        let mut visit_counts = [0; DIAL_SIZE as usize];
+        for rotation in rotations {
+            for _ in 0..rotation.distance {
+                position = match rotation.direction {
+                    Direction::Left => (position + DIAL_SIZE - 1) % DIAL_SIZE,
+                    Direction::Right => (position + 1) % DIAL_SIZE,
+                };
+                visit_counts[position as usize] += 1;
+            }
+        }
+        BatchResult {
+            crossings: visit_counts[0],
+            final_position: position,
+            visit_counts,
+        }
+    }
+
+    /// Small deterministic generator so the comparisons are reproducible.
+    fn pseudo_random(seed: &mut u64) -> u64 {
+        *seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        *seed >> 33
+    }
+
+    fn random_rotations(seed: &mut u64, count: usize, max_distance: u64) -> Vec<Rotation> {
+        (0..count)
+            .map(|_| Rotation {
+                direction: if pseudo_random(seed).is_multiple_of(2) {
+                    Direction::Left
+                } else {
+                    Direction::Right
+                },
+                distance: pseudo_random(seed) % (max_distance + 1),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn apply_batch_matches_click_by_click_reference() {
+        let mut seed = 42;
+        for _ in 0..200 {
+            let start = (pseudo_random(&mut seed) % u64::from(DIAL_SIZE)) as u32;
+            let rotations = random_rotations(&mut seed, 8, 350);
+            let mut safe = Safe::starting_at(start);
+            assert_eq!(safe.apply_batch(&rotations), naive_batch(start, &rotations));
+            assert_eq!(safe.position, naive_batch(start, &rotations).final_position);
+        }
+    }
+
+    #[test]
+    fn apply_batch_crossings_match_part2() {
+        let input = "L68\nL30\nR48\nL5\nR60\nL55\nL1\nL99\nR14\nL82";
+        let rotations: Vec<Rotation> = input.lines().map(|l| parse_rotation(l).unwrap()).collect();
+        let result = Safe::default().apply_batch(&rotations);
+        assert_eq!(result.crossings, 6);
+        assert_eq!(result.visit_counts[0], 6);
+        assert_eq!(result.final_position, 32);
+    }
+
+    #[test]
+    fn apply_batch_handles_a_million_huge_rotations() {
+        let mut seed = 7;
+        let rotations = random_rotations(&mut seed, 1_000_000, 1_000_000_000);
+        let total_distance: u64 = rotations.iter().map(|r| r.distance).sum();
+
+        let result = Safe::default().apply_batch(&rotations);
+        assert_eq!(result.visit_counts.iter().sum::<u64>(), total_distance);
+        assert_eq!(result.crossings, result.visit_counts[0]);
+    }
+
+    #[test]
+    #[ignore] // Timing check; run with `cargo test --release -- --ignored`
+    fn apply_batch_handles_a_million_huge_rotations_quickly() {
+        let mut seed = 7;
+        let rotations = random_rotations(&mut seed, 1_000_000, 1_000_000_000);
+
+        let started = std::time::Instant::now();
+        Safe::default().apply_batch(&rotations);
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    }
+
+    #[test]
+    fn parse_instruction_recognizes_undo() {
+        assert_eq!(parse_instruction("U"), Ok(Instruction::Undo(1)));
+        assert_eq!(parse_instruction("U3"), Ok(Instruction::Undo(3)));
+        assert_eq!(
+            parse_instruction("U3x"),
+            Err(RotationParseError::TrailingGarbage { at: 2 })
+        );
+        assert!(matches!(
+            parse_instruction("L5"),
+            Ok(Instruction::Rotate(Rotation {
+                direction: Direction::Left,
+                distance: 5
+            }))
+        ));
+    }
+
+    #[test]
+    fn undo_restores_position_but_keeps_crossings() {
+        let input = "R50\nU";
+        let steps = trace(input).unwrap();
+        assert_eq!(steps.len(), 2);
+        assert!(steps[1].undo);
+        assert_eq!(steps[1].position_after, 50);
+        assert_eq!(solve_part2(input), 1);
+        assert_eq!(solve(input), 1);
+    }
+
+    #[test]
+    fn nested_undos_interleave_with_rotations() {
+        // 50 -R10-> 60 -R20-> 80 -U-> 60 -L30-> 30 -U2-> 50 -L50-> 0
+        let input = "R10\nR20\nU\nL30\nU2\nL50";
+        let steps = trace(input).unwrap();
+        let positions: Vec<u32> = steps.iter().map(|step| step.position_after).collect();
+        assert_eq!(positions, vec![60, 80, 60, 30, 60, 50, 0]);
+        assert_eq!(solve(input), 1);
+        assert_eq!(solve_part2(input), 1);
+    }
+
+    #[test]
+    fn reader_and_string_inputs_report_the_same_parse_error() {
+        let input = "L68\n\nX30\nR48";
+        let error = solve_from_reader(Cursor::new(input)).unwrap_err();
+        let from_reader = error.get_ref().unwrap().downcast_ref::<ParseError>();
+
+        let from_string = simulate(input, |_| {}).unwrap_err();
+        assert!(matches!(from_string, ParseError::Line { line: 3, .. }));
+        assert_eq!(from_reader, Some(&from_string));
+        assert_eq!(trace(input).unwrap_err(), from_string.to_string());
+    }
+
+    #[test]
+    fn undo_without_prior_rotation_is_an_error() {
+        let error = trace("U").unwrap_err();
+        assert!(error.starts_with("line 1:"), "{}", error);
+        assert!(trace("R5\nU2").is_err());
+
+        let error = solve_from_reader(Cursor::new("L1\nU\nU")).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("line 3"), "{}", error);
+    }
+
+    #[test]
+    fn analyze_ignores_undone_movement() {
+        let stats = analyze("R5\nU").unwrap();
+        assert_eq!(stats.total_right_distance, 5);
+        assert_eq!(stats.total_left_distance, 0);
+        assert_eq!(stats.longest_direction_run, 1);
+    }
+
+    #[test]
+    fn example_never_returns_to_start() {
+        let input = "L68\nL30\nR48\nL5\nR60\nL55\nL1\nL99\nR14\nL82";
+        assert_eq!(first_return_to_start(input), None);
+        assert_eq!(count_returns_to_start(input), 0);
+    }
+
+    #[test]
+    fn detects_first_and_total_returns_to_start() {
+        // 60, 50, 50 (a full turn), 50 (ten full turns)
+        let input = "R10\nL10\nR100\nL1000";
+        assert_eq!(first_return_to_start(input), Some(2));
+        assert_eq!(count_returns_to_start(input), 3);
+    }
+
+    #[test]
+    fn undo_counts_as_one_instruction() {
+        // 60, 50, 55, then U2 passes 50 on its way back to 60: no return there
+        let input = "R10\nL10\nR5\nU2";
+        assert_eq!(first_return_to_start(input), Some(2));
+        assert_eq!(count_returns_to_start(input), 1);
+
+        // 60, 65, then U2 ends back on 50 as the third instruction (fourth step)
+        let input = "R10\nR5\nU2\nL10";
+        assert_eq!(first_return_to_start(input), Some(3));
+        assert_eq!(count_returns_to_start(input), 1);
+    }
+
+    #[test]
+    fn passing_through_start_is_not_a_return() {
+        // 20, then sweeping over 50 to 80, then back over 50 to 20, many times
+        let input = "L30\nR60\nL60\nR60\nL60\nR160";
+        assert_eq!(first_return_to_start(input), None);
+        assert_eq!(count_returns_to_start(input), 0);
+    }
+
+    #[test]
+    fn simulate_reports_every_event_of_the_example() {
+        let input = "L68\nL30\nR48\nL5\nR60\nL55\nL1\nL99\nR14\nL82";
+        let mut events = Vec::new();
+        simulate(input, |event| events.push(event.clone())).unwrap();
+
+        // One Parsed and one Rotated per line, then Finished
+        assert_eq!(events.len(), 21);
+        assert_eq!(
+            events[0],
+            SimEvent::Parsed(Rotation {
+                direction: Direction::Left,
+                distance: 68
+            })
+        );
+        assert_eq!(
+            events[1],
+            SimEvent::Rotated {
+                from: 50,
+                to: 82,
+                crossings: 1
+            }
+        );
+        assert_eq!(events[20], SimEvent::Finished { final_position: 32 });
+    }
+
+    #[test]
+    fn simulate_stops_reporting_at_a_parse_error() {
+        let mut events = Vec::new();
+        let result = simulate("L68\nL30\nX48\nL5", |event| events.push(event.clone()));
+
+        assert_eq!(
+            result,
+            Err(ParseError::Line {
+                line: 3,
+                source: RotationParseError::BadDirection('X')
+            })
+        );
+        assert_eq!(events.len(), 4);
+        assert!(
+            !events
+                .iter()
+                .any(|event| matches!(event, SimEvent::Finished { .. }))
+        );
+    }
+
+    #[test]
+    fn simulate_reports_undone_rotations() {
+        let mut events = Vec::new();
+        simulate("R60\nU", |event| events.push(event.clone())).unwrap();
+        assert_eq!(events[2], SimEvent::Undone { from: 10, to: 50 });
+        assert_eq!(events[3], SimEvent::Finished { final_position: 50 });
+    }
+
+    #[test]
+    fn simulate_reports_excessive_undo() {
+        let result = simulate("R10\nU2", |_| {});
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "line 2: cannot undo 2 rotation(s), only 1 to undo"
+        );
+    }
 }
@@ -1,10 +1,63 @@
-use day1::{solve, solve_part2};
-use std::fs;
+use day1::{solve_from_reader, solve_part2_from_reader};
+use std::env;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::process;
+
+const USAGE: &str = "usage: day1 [PATH] [--part 1|2]";
+
+struct Options {
+    path: String,
+    part: Option<u8>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut path = None;
+    let mut part = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--part" => match args.next().as_deref() {
+                Some("1") => part = Some(1),
+                Some("2") => part = Some(2),
+                Some(other) => return Err(format!("invalid part {:?}, expected 1 or 2", other)),
+                None => return Err("--part needs a value".to_string()),
+            },
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(format!("unexpected argument {:?}", arg)),
+        }
+    }
+
+    Ok(Options {
+        path: path.unwrap_or_else(|| "rotations.txt".to_string()),
+        part,
+    })
+}
+
+fn run<T: Display>(path: &str, solver: fn(BufReader<File>) -> io::Result<T>) -> T {
+    let file = File::open(path).unwrap_or_else(|err| {
+        eprintln!("Failed to read {}: {}", path, err);
+        process::exit(1);
+    });
+    solver(BufReader::new(file)).unwrap_or_else(|err| {
+        eprintln!("Failed to solve {}: {}", path, err);
+        process::exit(1);
+    })
+}
 
 fn main() {
-    let input = fs::read_to_string("rotations.txt").expect("Failed to read input file");
-    let result = solve(&input);
-    println!("Part 1 Answer: {}", result);
-    let result_part2 = solve_part2(&input);
-    println!("Part 2 Answer: {}", result_part2);
+    let options = parse_args(env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("{}\n{}", err, USAGE);
+        process::exit(2);
+    });
+
+    if options.part != Some(2) {
+        let result = run(&options.path, solve_from_reader);
+        println!("Part 1 Answer: {}", result);
+    }
+    if options.part != Some(1) {
+        let result_part2 = run(&options.path, solve_part2_from_reader);
+        println!("Part 2 Answer: {}", result_part2);
+    }
 }
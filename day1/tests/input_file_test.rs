@@ -0,0 +1,75 @@
+use day1::{solve_from_reader, solve_part2_from_reader};
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::PathBuf;
+use std::process::Command;
+
+const EXAMPLE: &str = "L68\nL30\nR48\nL5\nR60\nL55\nL1\nL99\nR14\nL82\n";
+
+fn temp_input(name: &str, contents: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("day1-{}-{}.txt", name, std::process::id()));
+    fs::write(&path, contents).expect("Failed to write temp input");
+    path
+}
+
+fn open(path: &PathBuf) -> BufReader<File> {
+    BufReader::new(File::open(path).expect("Failed to open temp input"))
+}
+
+fn run_binary(args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_day1"))
+        .args(args)
+        .output()
+        .expect("Failed to run day1 binary")
+}
+
+#[test]
+fn solves_example_from_file() {
+    let path = temp_input("example", EXAMPLE);
+    assert_eq!(solve_from_reader(open(&path)).unwrap(), 3);
+    assert_eq!(solve_part2_from_reader(open(&path)).unwrap(), 6);
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn malformed_file_is_invalid_data() {
+    let path = temp_input("malformed", "L68\nL3O\n");
+    let error = solve_from_reader(open(&path)).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    assert!(error.to_string().contains("line 2"), "{}", error);
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn binary_prints_only_the_requested_part() {
+    let path = temp_input("binary-part", EXAMPLE);
+    let output = run_binary(&[path.to_str().unwrap(), "--part", "2"]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "Part 2 Answer: 6\n"
+    );
+
+    let output = run_binary(&["--part", "1", path.to_str().unwrap()]);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "Part 1 Answer: 3\n"
+    );
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn binary_fails_on_missing_file() {
+    let output = run_binary(&["/nonexistent/rotations.txt"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Failed to read"));
+}
+
+#[test]
+fn binary_fails_on_malformed_line() {
+    let path = temp_input("binary-malformed", "L68\nX1\n");
+    let output = run_binary(&[path.to_str().unwrap()]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("line 2"));
+    fs::remove_file(path).unwrap();
+}
@@ -1,246 +1,2829 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
 pub fn is_invalid_id(id: u64) -> bool {
-    let s = id.to_string();
-    let len = s.len();
+    is_invalid_id_in_base(id, 10)
+}
+
+pub fn is_invalid_id_part2(id: u64) -> bool {
+    invalid_pattern(id).is_some()
+}
+
+/// Same answer as [`is_invalid_id_part2`] in a single pass over the digits:
+/// with `border` the longest proper prefix that is also a suffix (the last
+/// entry of the KMP failure function), `n` digits are a repeated block iff
+/// `border > 0` and `n - border` divides `n`.
+pub fn is_invalid_id_part2_fast(id: u64) -> bool {
+    let mut buf = [0; MAX_RADIX_DIGITS];
+    let digits = digits_of(id, 10, &mut buf);
+    let n = digits.len();
+
+    let mut fail = [0; MAX_RADIX_DIGITS];
+    let mut border = 0;
+    for i in 1..n {
+        while border > 0 && digits[i] != digits[border] {
+            border = fail[border - 1];
+        }
+        if digits[i] == digits[border] {
+            border += 1;
+        }
+        fail[i] = border;
+    }
+
+    border > 0 && n.is_multiple_of(n - border)
+}
+
+/// Like [`is_invalid_id`], but looks at the digits of `id` written in `base`.
+/// Base 10 gives exactly [`is_invalid_id`].
+///
+/// # Panics
+/// Panics if `base` is not in `2..=36`.
+pub fn is_invalid_id_in_base(id: u64, base: u32) -> bool {
+    is_half_repeat(digits_of(id, base, &mut [0; MAX_RADIX_DIGITS]))
+}
+
+/// Like [`is_invalid_id_part2`], but looks at the digits of `id` written in `base`.
+///
+/// # Panics
+/// Panics if `base` is not in `2..=36`.
+pub fn is_invalid_id_part2_in_base(id: u64, base: u32) -> bool {
+    is_any_repeat(digits_of(id, base, &mut [0; MAX_RADIX_DIGITS]))
+}
+
+/// Same as [`is_invalid_id_in_base`], under its former name.
+#[deprecated(note = "use `is_invalid_id_in_base`")]
+pub fn is_invalid_id_radix(id: u64, radix: u32) -> bool {
+    is_invalid_id_in_base(id, radix)
+}
+
+/// Same as [`is_invalid_id_part2_in_base`], under its former name.
+#[deprecated(note = "use `is_invalid_id_part2_in_base`")]
+pub fn is_invalid_id_part2_radix(id: u64, radix: u32) -> bool {
+    is_invalid_id_part2_in_base(id, radix)
+}
+
+/// Bonus rule: an ID is invalid when its decimal digits read the same both
+/// ways and there are at least two of them. 121 is invalid, 5 and 10 are not.
+pub fn is_invalid_id_palindrome(id: u64) -> bool {
+    let mut buf = [0; MAX_RADIX_DIGITS];
+    let digits = digits_of(id, 10, &mut buf);
+    digits.len() >= 2 && digits.iter().eq(digits.iter().rev())
+}
+
+/// Most digits a `u64` can have in any radix (64, in binary).
+const MAX_RADIX_DIGITS: usize = 64;
+
+/// Writes the digits of `id` in `radix` at the end of `buf` and returns them,
+/// most significant first, so the validators never allocate.
+fn digits_of(mut id: u64, radix: u32, buf: &mut [u8; MAX_RADIX_DIGITS]) -> &[u8] {
+    assert!(
+        (2..=36).contains(&radix),
+        "radix must be in 2..=36, got {}",
+        radix
+    );
+    let radix = u64::from(radix);
+    let mut start = buf.len();
+    loop {
+        start -= 1;
+        buf[start] = (id % radix) as u8;
+        id /= radix;
+        if id == 0 {
+            break;
+        }
+    }
+    &buf[start..]
+}
+
+/// True when the digits are some sequence repeated exactly twice.
+fn is_half_repeat(digits: &[u8]) -> bool {
+    let len = digits.len();
 
     if !len.is_multiple_of(2) {
         return false;
     }
 
-    let mid = len / 2;
-    let first_half = &s[..mid];
-    let second_half = &s[mid..];
+    let (first_half, second_half) = digits.split_at(len / 2);
+    first_half == second_half
+}
+
+/// True when the digits are some sequence repeated at least twice.
+fn is_any_repeat(digits: &[u8]) -> bool {
+    smallest_repeating_block(digits).is_some()
+}
+
+/// Returns the shortest block that, repeated at least twice, spells `id`,
+/// together with its repetition count. `1111` gives `("1", 4)`.
+pub fn repeating_pattern(id: u64) -> Option<(String, usize)> {
+    let mut buf = [0; MAX_RADIX_DIGITS];
+    let digits = digits_of(id, 10, &mut buf);
+    smallest_repeating_block(digits).map(|k| {
+        let block = digits[..k].iter().map(|&d| char::from(b'0' + d)).collect();
+        (block, digits.len() / k)
+    })
+}
+
+/// Why an ID is invalid under the Part 2 rule: `pattern` written
+/// `repetitions` times spells it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PatternMatch {
+    pub pattern: u64,
+    /// Number of digits in `pattern`.
+    pub length: usize,
+    pub repetitions: usize,
+}
+
+/// Explains a Part 2 invalid ID with its shortest repeating pattern:
+/// 1111 is `1` four times rather than `11` twice. `None` for valid IDs.
+pub fn invalid_pattern(id: u64) -> Option<PatternMatch> {
+    let mut buf = [0; MAX_RADIX_DIGITS];
+    let digits = digits_of(id, 10, &mut buf);
+    smallest_repeating_block(digits).map(|k| PatternMatch {
+        pattern: digits[..k]
+            .iter()
+            .fold(0, |value, &d| value * 10 + u64::from(d)),
+        length: k,
+        repetitions: digits.len() / k,
+    })
+}
+
+/// Pairs each Part 2 invalid ID of `range` with its [`invalid_pattern`].
+pub fn explain_range(range: &Range) -> Vec<(u64, PatternMatch)> {
+    (range.start..=range.end)
+        .filter_map(|id| invalid_pattern(id).map(|pattern| (id, pattern)))
+        .collect()
+}
+
+/// Length of the shortest block repeated at least twice to form `digits`, if any.
+fn smallest_repeating_block(digits: &[u8]) -> Option<usize> {
+    let len = digits.len();
+
+    // Try all possible pattern lengths from 1 to len/2
+    // Only consider lengths that divide the total length
+    (1..=len / 2).filter(|&k| len.is_multiple_of(k)).find(|&k| {
+        let pattern = &digits[..k];
+        digits.chunks(k).all(|chunk| chunk == pattern)
+    })
+}
+
+/// The IDs `start..=end`. A range whose `start` is past its `end` is empty.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Range {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl Range {
+    /// Number of IDs in the range; a `u128` so `0-18446744073709551615` fits.
+    pub fn len(&self) -> u128 {
+        if self.is_empty() {
+            0
+        } else {
+            u128::from(self.end - self.start) + 1
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start > self.end
+    }
+
+    pub fn contains(&self, id: u64) -> bool {
+        (self.start..=self.end).contains(&id)
+    }
+
+    /// The IDs in both ranges, if any.
+    pub fn intersect(&self, other: &Range) -> Option<Range> {
+        let range = Range {
+            start: self.start.max(other.start),
+            end: self.end.min(other.end),
+        };
+        (!range.is_empty()).then_some(range)
+    }
+
+    /// The IDs of the range in ascending order.
+    pub fn iter(&self) -> std::ops::RangeInclusive<u64> {
+        self.start..=self.end
+    }
+}
+
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
+impl FromStr for Range {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = input.split('-').collect();
+        if parts.len() != 2 {
+            return Err("Invalid range format".to_string());
+        }
+
+        let start = parts[0]
+            .trim()
+            .parse::<u64>()
+            .map_err(|_| "Invalid start number".to_string())?;
+        let end = parts[1]
+            .trim()
+            .parse::<u64>()
+            .map_err(|_| "Invalid end number".to_string())?;
+
+        if start > end {
+            return Err("start greater than end".to_string());
+        }
+
+        Ok(Range { start, end })
+    }
+}
+
+pub fn parse_range(input: &str) -> Result<Range, String> {
+    input.parse()
+}
+
+/// A segment of the input that is not a valid range.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangeParseError {
+    /// Zero-based position of the segment among all segments of the input.
+    pub index: usize,
+    /// The segment as written, surrounding whitespace removed.
+    pub text: String,
+    /// Why [`parse_range`] rejected it.
+    pub reason: String,
+}
+
+impl fmt::Display for RangeParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "range {} {:?}: {}", self.index, self.text, self.reason)
+    }
+}
+
+impl Error for RangeParseError {}
+
+/// Splits `input` into range segments: ranges are separated by commas or
+/// newlines, and blank lines and lines starting with `#` are ignored.
+fn input_segments(input: &str) -> impl Iterator<Item = &str> {
+    input
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .flat_map(|line| line.split(','))
+}
+
+/// Parses every segment of `input`, tagging failures with their position.
+fn parse_ranges(input: &str) -> impl Iterator<Item = Result<Range, RangeParseError>> + '_ {
+    input_segments(input).enumerate().map(|(index, range_str)| {
+        let text = range_str.trim();
+        parse_range(text).map_err(|reason| RangeParseError {
+            index,
+            text: text.to_string(),
+            reason,
+        })
+    })
+}
+
+/// The segments of `input` that parse as ranges; the others are skipped.
+fn valid_ranges(input: &str) -> impl Iterator<Item = Range> + '_ {
+    parse_ranges(input).filter_map(Result::ok)
+}
+
+/// Lazily yields the IDs of `range` accepted by `validator`, in ascending order.
+/// Iterating from the back (`.rev()`) finds the largest ones just as cheaply.
+pub fn iter_ids_in_range<'a, F>(
+    range: &Range,
+    validator: F,
+) -> impl DoubleEndedIterator<Item = u64> + use<'a, F>
+where
+    F: Fn(u64) -> bool + 'a,
+{
+    range.iter().filter(move |&id| validator(id))
+}
+
+/// Lazily yields the Part 1 invalid IDs of `range`.
+pub fn iter_invalid_ids<'a>(range: &Range) -> impl DoubleEndedIterator<Item = u64> + use<'a> {
+    iter_ids_in_range(range, is_invalid_id)
+}
+
+/// Lazily yields the Part 2 invalid IDs of `range`.
+pub fn iter_invalid_ids_part2<'a>(range: &Range) -> impl DoubleEndedIterator<Item = u64> + use<'a> {
+    iter_ids_in_range(range, is_invalid_id_part2)
+}
+
+/// Same as [`iter_ids_in_range`], under its former name.
+#[deprecated(note = "use `iter_ids_in_range`")]
+pub fn invalid_ids<'a>(
+    range: &Range,
+    validator: impl Fn(u64) -> bool + 'a,
+) -> impl DoubleEndedIterator<Item = u64> + 'a {
+    iter_ids_in_range(range, validator)
+}
+
+pub fn find_ids_in_range<F>(range: &Range, validator: F) -> Vec<u64>
+where
+    F: Fn(u64) -> bool + Copy,
+{
+    iter_ids_in_range(range, validator).collect()
+}
+
+pub fn find_invalid_ids_in_range(range: &Range) -> Vec<u64> {
+    find_ids_in_range(range, is_invalid_id)
+}
+
+/// Number of IDs of `range` accepted by `validator`, without collecting them.
+pub fn count_ids_in_range<F>(range: &Range, validator: F) -> u64
+where
+    F: Fn(u64) -> bool + Copy,
+{
+    iter_ids_in_range(range, validator).count() as u64
+}
+
+/// Sum of the IDs of `range` accepted by `validator`, without collecting them.
+pub fn sum_ids_in_range<F>(range: &Range, validator: F) -> u64
+where
+    F: Fn(u64) -> bool + Copy,
+{
+    iter_ids_in_range(range, validator).sum()
+}
+
+/// Sums the IDs accepted by `validator` over every range of the input.
+/// Ranges that fail to parse (including reversed ones) are skipped.
+pub fn solve_with_validator<F>(input: &str, validator: F) -> u64
+where
+    F: Fn(u64) -> bool + Copy,
+{
+    solve_breakdown(input, validator)
+        .into_iter()
+        .map(|(_, sum)| sum)
+        .sum()
+}
+
+/// Like [`solve_with_validator`], scanning the ranges on the rayon pool.
+/// Ranges that fail to parse (including reversed ones) are skipped.
+#[cfg(feature = "rayon")]
+pub fn solve_with_validator_parallel<F>(input: &str, validator: F) -> u64
+where
+    F: Fn(u64) -> bool + Copy + Send + Sync,
+{
+    use rayon::prelude::*;
+
+    valid_ranges(input)
+        .collect::<Vec<_>>()
+        .into_par_iter()
+        .map(|range| sum_ids_in_range(&range, validator))
+        .sum()
+}
+
+/// Tuning for [`solve_parallel_with`].
+#[cfg(feature = "rayon")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SolveOptions {
+    /// Ranges with more IDs than this are split into chunks of this size,
+    /// so one huge range does not end up on a single thread.
+    pub chunk_size: u64,
+}
+
+#[cfg(feature = "rayon")]
+impl Default for SolveOptions {
+    fn default() -> Self {
+        Self {
+            chunk_size: 1_000_000,
+        }
+    }
+}
+
+/// Like [`solve`], scanning ranges (and chunks of large ranges) on the rayon pool.
+#[cfg(feature = "rayon")]
+pub fn solve_parallel(input: &str) -> u64 {
+    solve_parallel_with(input, is_invalid_id, SolveOptions::default())
+}
+
+/// Like [`solve_part2`], scanning ranges (and chunks of large ranges) on the rayon pool.
+#[cfg(feature = "rayon")]
+pub fn solve_part2_parallel(input: &str) -> u64 {
+    solve_parallel_with(input, is_invalid_id_part2_fast, SolveOptions::default())
+}
+
+/// Sums the IDs accepted by `validator` like [`solve_with_validator`], after
+/// cutting every range into chunks of at most `options.chunk_size` IDs.
+/// Ranges that fail to parse are skipped.
+#[cfg(feature = "rayon")]
+pub fn solve_parallel_with<F>(input: &str, validator: F, options: SolveOptions) -> u64
+where
+    F: Fn(u64) -> bool + Copy + Send + Sync,
+{
+    use rayon::prelude::*;
+
+    let chunks: Vec<Range> = valid_ranges(input)
+        .flat_map(|range| range_chunks(range, options.chunk_size))
+        .collect();
+    chunks
+        .into_par_iter()
+        .map(|chunk| sum_ids_in_range(&chunk, validator))
+        .sum()
+}
+
+/// Splits `range` into consecutive ranges of at most `chunk_size` IDs.
+#[cfg(feature = "rayon")]
+fn range_chunks(range: Range, chunk_size: u64) -> impl Iterator<Item = Range> {
+    let chunk_size = chunk_size.max(1);
+    std::iter::successors(Some(range.start), move |&start| {
+        start
+            .checked_add(chunk_size)
+            .filter(|&next| next <= range.end)
+    })
+    .map(move |start| Range {
+        start,
+        end: start.saturating_add(chunk_size - 1).min(range.end),
+    })
+}
+
+/// Sorts `ranges` and merges the overlapping or adjacent ones, so every ID is
+/// covered by exactly one of the returned ranges.
+pub fn normalize_ranges(ranges: &[Range]) -> Vec<Range> {
+    let mut sorted = ranges.to_vec();
+    sorted.sort_by_key(|range| (range.start, range.end));
+
+    let mut merged: Vec<Range> = Vec::with_capacity(sorted.len());
+    for range in sorted {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end.saturating_add(1) => {
+                last.end = last.end.max(range.end);
+            }
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// Like [`solve_with_validator`], but counts each ID once even when ranges
+/// overlap. Every range must parse; the first bad one is reported.
+pub fn solve_deduplicated<F>(input: &str, validator: F) -> Result<u64, RangeParseError>
+where
+    F: Fn(u64) -> bool + Copy,
+{
+    let ranges = parse_ranges(input).collect::<Result<Vec<_>, _>>()?;
+
+    Ok(normalize_ranges(&ranges)
+        .iter()
+        .map(|range| sum_ids_in_range(range, validator))
+        .sum())
+}
+
+/// Returns each successfully parsed range with the sum of its IDs accepted by
+/// `validator`, in input order.
+pub fn solve_breakdown<F>(input: &str, validator: F) -> Vec<(Range, u64)>
+where
+    F: Fn(u64) -> bool + Copy,
+{
+    valid_ranges(input)
+        .map(|range| {
+            let sum = sum_ids_in_range(&range, validator);
+            (range, sum)
+        })
+        .collect()
+}
+
+/// Why [`solve_checked`] could not produce a sum.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SolveError {
+    Parse(RangeParseError),
+    /// The sum no longer fits in a `u64` while adding the IDs of the range at
+    /// `range_index`; `partial_sum` is the total just before that.
+    Overflow {
+        range_index: usize,
+        partial_sum: u64,
+    },
+}
+
+impl fmt::Display for SolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SolveError::Parse(e) => write!(f, "{}", e),
+            SolveError::Overflow {
+                range_index,
+                partial_sum,
+            } => write!(
+                f,
+                "sum overflows u64 in range {} (partial sum {})",
+                range_index, partial_sum
+            ),
+        }
+    }
+}
+
+impl Error for SolveError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SolveError::Parse(e) => Some(e),
+            SolveError::Overflow { .. } => None,
+        }
+    }
+}
+
+impl From<RangeParseError> for SolveError {
+    fn from(e: RangeParseError) -> Self {
+        SolveError::Parse(e)
+    }
+}
+
+/// Like [`solve_with_validator`], but reports a sum that does not fit in a
+/// `u64` instead of wrapping, and fails on segments that are not ranges.
+pub fn solve_checked<F>(input: &str, validator: F) -> Result<u64, SolveError>
+where
+    F: Fn(u64) -> bool + Copy,
+{
+    let mut total: u64 = 0;
+    for (range_index, range) in parse_ranges(input).enumerate() {
+        let range = range?;
+        for id in iter_ids_in_range(&range, validator) {
+            total = total.checked_add(id).ok_or(SolveError::Overflow {
+                range_index,
+                partial_sum: total,
+            })?;
+        }
+    }
+    Ok(total)
+}
+
+/// Like [`solve_with_validator`], summing into a `u128` so the total is exact.
+/// Ranges that fail to parse are skipped.
+pub fn solve_u128<F>(input: &str, validator: F) -> u128
+where
+    F: Fn(u64) -> bool + Copy,
+{
+    valid_ranges(input)
+        .flat_map(|range| iter_ids_in_range(&range, validator))
+        .map(u128::from)
+        .sum()
+}
+
+/// What one input range contains, as gathered by [`solve_report`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangeReport {
+    pub range: Range,
+    /// How many IDs of the range the validator accepted.
+    pub count: u64,
+    /// Sum of the accepted IDs, exact even where it passes `u64::MAX`.
+    pub sum: u128,
+    /// Smallest accepted ID, `None` when there is none.
+    pub min: Option<u64>,
+    /// Largest accepted ID, `None` when there is none.
+    pub max: Option<u64>,
+}
+
+/// Reports, for every range in input order, the IDs accepted by `validator`.
+/// Fails on the first segment that is not a valid range.
+pub fn solve_report(
+    input: &str,
+    validator: impl Fn(u64) -> bool + Copy,
+) -> Result<Vec<RangeReport>, RangeParseError> {
+    parse_ranges(input)
+        .map(|range| {
+            let range = range?;
+            let mut report = RangeReport {
+                range,
+                count: 0,
+                sum: 0,
+                min: None,
+                max: None,
+            };
+            for id in iter_ids_in_range(&range, validator) {
+                report.count += 1;
+                report.sum += u128::from(id);
+                report.min.get_or_insert(id);
+                report.max = Some(id);
+            }
+            Ok(report)
+        })
+        .collect()
+}
+
+/// The `k` smallest and `k` largest IDs found by [`extreme_invalid_ids`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Extremes {
+    /// In ascending order.
+    pub smallest: Vec<u64>,
+    /// In descending order, the largest first.
+    pub largest: Vec<u64>,
+}
+
+/// Finds the `k` smallest and `k` largest IDs accepted by `validator` across
+/// all ranges, counting an ID once even when ranges overlap. Only `k` IDs are
+/// kept on each side while scanning; fewer are returned when fewer match.
+/// Fails on the first segment that is not a valid range.
+pub fn extreme_invalid_ids(
+    input: &str,
+    validator: impl Fn(u64) -> bool + Copy,
+    k: usize,
+) -> Result<Extremes, RangeParseError> {
+    let ranges = parse_ranges(input).collect::<Result<Vec<_>, _>>()?;
+    if k == 0 {
+        return Ok(Extremes::default());
+    }
+
+    // The smallest IDs sit in a max-heap and the largest in a min-heap, so the
+    // root is always the kept ID the next candidate has to beat.
+    let mut smallest = BinaryHeap::with_capacity(k + 1);
+    let mut largest = BinaryHeap::with_capacity(k + 1);
+    for range in normalize_ranges(&ranges) {
+        for id in iter_ids_in_range(&range, validator) {
+            smallest.push(id);
+            if smallest.len() > k {
+                smallest.pop();
+            }
+            largest.push(Reverse(id));
+            if largest.len() > k {
+                largest.pop();
+            }
+        }
+    }
+
+    Ok(Extremes {
+        smallest: smallest.into_sorted_vec(),
+        largest: largest
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(id)| id)
+            .collect(),
+    })
+}
+
+pub fn solve(input: &str) -> u64 {
+    solve_with_validator(input, is_invalid_id)
+}
+
+/// A rule deciding which IDs are invalid. Unlike the plain `Fn(u64) -> bool`
+/// validators, implementors may keep state between calls.
+pub trait IdValidator {
+    fn is_invalid(&mut self, id: u64) -> bool;
+    fn name(&self) -> &str;
+}
+
+/// Any `FnMut(u64) -> bool`, such as [`is_invalid_id`], is a validator.
+impl<F: FnMut(u64) -> bool> IdValidator for F {
+    fn is_invalid(&mut self, id: u64) -> bool {
+        self(id)
+    }
+
+    fn name(&self) -> &str {
+        "closure"
+    }
+}
+
+/// The Part 1 rule, reusing one digit buffer across calls.
+#[derive(Debug, Clone)]
+pub struct HalfRepeatValidator {
+    buf: [u8; MAX_RADIX_DIGITS],
+}
+
+impl Default for HalfRepeatValidator {
+    fn default() -> Self {
+        Self {
+            buf: [0; MAX_RADIX_DIGITS],
+        }
+    }
+}
+
+impl IdValidator for HalfRepeatValidator {
+    fn is_invalid(&mut self, id: u64) -> bool {
+        is_half_repeat(digits_of(id, 10, &mut self.buf))
+    }
+
+    fn name(&self) -> &str {
+        "half repeat"
+    }
+}
+
+/// The Part 2 rule, reusing one digit buffer across calls.
+#[derive(Debug, Clone)]
+pub struct AnyRepeatValidator {
+    buf: [u8; MAX_RADIX_DIGITS],
+}
+
+impl Default for AnyRepeatValidator {
+    fn default() -> Self {
+        Self {
+            buf: [0; MAX_RADIX_DIGITS],
+        }
+    }
+}
+
+impl IdValidator for AnyRepeatValidator {
+    fn is_invalid(&mut self, id: u64) -> bool {
+        is_any_repeat(digits_of(id, 10, &mut self.buf))
+    }
+
+    fn name(&self) -> &str {
+        "any repeat"
+    }
+}
+
+/// Flags an ID when any of its validators does.
+pub struct CompositeValidator {
+    validators: Vec<Box<dyn IdValidator>>,
+    name: String,
+}
+
+impl CompositeValidator {
+    pub fn new(validators: Vec<Box<dyn IdValidator>>) -> Self {
+        let name = validators
+            .iter()
+            .map(|validator| validator.name())
+            .collect::<Vec<_>>()
+            .join(" or ");
+        Self { validators, name }
+    }
+}
+
+impl IdValidator for CompositeValidator {
+    fn is_invalid(&mut self, id: u64) -> bool {
+        self.validators
+            .iter_mut()
+            .any(|validator| validator.is_invalid(id))
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// Sums the IDs flagged by `validator` over every range of the input.
+/// Ranges that fail to parse are skipped.
+pub fn solve_with(input: &str, validator: &mut impl IdValidator) -> u64 {
+    let mut total = 0;
+    for range in valid_ranges(input) {
+        for id in range.start..=range.end {
+            if validator.is_invalid(id) {
+                total += id;
+            }
+        }
+    }
+    total
+}
+
+/// Like [`solve`], with IDs checked in `base` by [`is_invalid_id_in_base`].
+///
+/// # Panics
+/// Panics if `base` is not in `2..=36`.
+pub fn solve_in_base(input: &str, base: u32) -> u64 {
+    solve_with_validator(input, move |id| is_invalid_id_in_base(id, base))
+}
+
+/// Like [`solve_part2`], with IDs checked in `base` by [`is_invalid_id_part2_in_base`].
+///
+/// # Panics
+/// Panics if `base` is not in `2..=36`.
+pub fn solve_part2_in_base(input: &str, base: u32) -> u64 {
+    solve_with_validator(input, move |id| is_invalid_id_part2_in_base(id, base))
+}
+
+/// Like [`solve`], but fails on the first segment that is not a valid range
+/// instead of skipping it.
+pub fn try_solve(input: &str) -> Result<u64, RangeParseError> {
+    try_solve_with_validator(input, is_invalid_id)
+}
+
+/// Like [`solve_part2`], but fails on the first segment that is not a valid range.
+pub fn try_solve_part2(input: &str) -> Result<u64, RangeParseError> {
+    try_solve_with_validator(input, is_invalid_id_part2)
+}
+
+/// Like [`solve_with_validator`], but fails on the first bad segment.
+pub fn try_solve_with_validator<F>(input: &str, validator: F) -> Result<u64, RangeParseError>
+where
+    F: Fn(u64) -> bool + Copy,
+{
+    parse_ranges(input).try_fold(0, |total, range| {
+        Ok(total + sum_ids_in_range(&range?, validator))
+    })
+}
+
+/// Like [`try_solve_with_validator`], but reports every bad segment at once.
+pub fn try_solve_collecting_errors<F>(
+    input: &str,
+    validator: F,
+) -> Result<u64, Vec<RangeParseError>>
+where
+    F: Fn(u64) -> bool + Copy,
+{
+    let (ranges, errors): (Vec<_>, Vec<_>) = parse_ranges(input).partition(Result::is_ok);
+    if !errors.is_empty() {
+        return Err(errors.into_iter().filter_map(Result::err).collect());
+    }
+    Ok(ranges
+        .into_iter()
+        .filter_map(Result::ok)
+        .map(|range| sum_ids_in_range(&range, validator))
+        .sum())
+}
+
+/// How far a [`SolveSession`] got when its time budget ran out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionStatus {
+    /// Every range is scanned; the Part 1 answer.
+    Done(u64),
+    /// `ranges_done` ranges are fully scanned, and the next one up to
+    /// `current_offset` IDs past its start.
+    InProgress {
+        ranges_done: usize,
+        current_offset: u64,
+    },
+}
+
+/// A Part 1 scan that can be stopped after a time budget, saved with
+/// [`SolveSession::serialize`] and picked up later with [`SolveSession::resume`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SolveSession {
+    ranges: Vec<Range>,
+    ranges_done: usize,
+    current_offset: u64,
+    sum: u64,
+}
+
+impl SolveSession {
+    /// IDs scanned between two looks at the clock.
+    const BATCH: u64 = 64;
+    const MAGIC: &[u8; 4] = b"D2S1";
+
+    /// Starts a scan of `input`, failing on the first segment that is not a
+    /// valid range, like [`try_solve`].
+    pub fn new(input: &str) -> Result<Self, RangeParseError> {
+        Ok(SolveSession {
+            ranges: parse_ranges(input).collect::<Result<_, _>>()?,
+            ranges_done: 0,
+            current_offset: 0,
+            sum: 0,
+        })
+    }
+
+    /// Scans until the input is exhausted or `budget` has elapsed. At least
+    /// one batch of IDs is scanned per call, so a session always progresses.
+    pub fn run_for(&mut self, budget: Duration) -> SessionStatus {
+        let deadline = Instant::now().checked_add(budget);
+        while self.ranges_done < self.ranges.len() {
+            self.scan_batch();
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                break;
+            }
+        }
+        self.status()
+    }
+
+    pub fn status(&self) -> SessionStatus {
+        if self.ranges_done == self.ranges.len() {
+            SessionStatus::Done(self.sum)
+        } else {
+            SessionStatus::InProgress {
+                ranges_done: self.ranges_done,
+                current_offset: self.current_offset,
+            }
+        }
+    }
+
+    fn scan_batch(&mut self) {
+        let range = self.ranges[self.ranges_done];
+        let start = range.start + self.current_offset;
+        let end = start.saturating_add(Self::BATCH - 1).min(range.end);
+        self.sum += sum_ids_in_range(&Range { start, end }, is_invalid_id);
+
+        if end == range.end {
+            self.ranges_done += 1;
+            self.current_offset = 0;
+        } else {
+            self.current_offset = end - range.start + 1;
+        }
+    }
+
+    /// Encodes the session: a 4-byte tag, then the progress, the partial sum,
+    /// the number of ranges and every range bound, as little-endian `u64`s.
+    pub fn serialize(&self) -> Vec<u8> {
+        let header = [
+            self.ranges_done as u64,
+            self.current_offset,
+            self.sum,
+            self.ranges.len() as u64,
+        ];
+        let bounds = self
+            .ranges
+            .iter()
+            .flat_map(|range| [range.start, range.end]);
+
+        let mut bytes = Self::MAGIC.to_vec();
+        for value in header.into_iter().chain(bounds) {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Rebuilds a session written by [`SolveSession::serialize`].
+    pub fn resume(bytes: &[u8]) -> Result<Self, String> {
+        let body = bytes
+            .strip_prefix(Self::MAGIC.as_slice())
+            .ok_or("not a saved solve session")?;
+        if body.len() % 8 != 0 {
+            return Err("truncated solve session".to_string());
+        }
+        let values: Vec<u64> = body
+            .chunks_exact(8)
+            .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+
+        let [ranges_done, current_offset, sum, range_count, bounds @ ..] = values.as_slice() else {
+            return Err("truncated solve session".to_string());
+        };
+        if bounds.len() as u64 != range_count.saturating_mul(2) {
+            return Err(format!(
+                "expected {} ranges, found {} bounds",
+                range_count,
+                bounds.len()
+            ));
+        }
+        let ranges: Vec<Range> = bounds
+            .chunks_exact(2)
+            .map(|pair| Range {
+                start: pair[0],
+                end: pair[1],
+            })
+            .collect();
+        if let Some(range) = ranges.iter().find(|range| range.start > range.end) {
+            return Err(format!("range {} has start greater than end", range));
+        }
+
+        let ranges_done = *ranges_done as usize;
+        let in_bounds = match ranges.get(ranges_done) {
+            Some(range) => *current_offset <= range.end - range.start,
+            None => ranges_done == ranges.len() && *current_offset == 0,
+        };
+        if !in_bounds {
+            return Err("progress lies outside the saved ranges".to_string());
+        }
+
+        Ok(SolveSession {
+            ranges,
+            ranges_done,
+            current_offset: *current_offset,
+            sum: *sum,
+        })
+    }
+}
+
+pub fn find_invalid_ids_in_range_part2(range: &Range) -> Vec<u64> {
+    find_ids_in_range(range, is_invalid_id_part2)
+}
+
+pub fn solve_part2(input: &str) -> u64 {
+    solve_with_validator(input, is_invalid_id_part2_fast)
+}
+
+/// Number of decimal digits in `u64::MAX`, the longest ID we can be asked about.
+const MAX_DIGITS: u32 = 20;
+
+/// Same result as [`find_invalid_ids_in_range`], built without scanning the
+/// range: for each half length `k`, the invalid IDs are exactly
+/// `h * (10^k + 1)` for the `k`-digit halves `h` that land inside the range.
+pub fn find_invalid_ids_in_range_fast(range: &Range) -> Vec<u64> {
+    (1..=MAX_DIGITS / 2)
+        .flat_map(|half_len| block_repeats(range, half_len, 2))
+        .collect()
+}
+
+/// Same result as [`solve`], using [`find_invalid_ids_in_range_fast`] so huge
+/// ranges are cheap. Ranges that fail to parse are skipped.
+pub fn solve_fast(input: &str) -> u64 {
+    valid_ranges(input)
+        .map(|range| find_invalid_ids_in_range_fast(&range).iter().sum::<u64>())
+        .sum()
+}
+
+/// Same result as [`find_invalid_ids_in_range_part2`], built without scanning
+/// the range: every block repeated at least twice is generated, then the IDs
+/// reachable several ways (1111 is "1" x 4 and "11" x 2) are deduplicated.
+pub fn find_invalid_ids_in_range_part2_fast(range: &Range) -> Vec<u64> {
+    let mut ids: Vec<u64> = (2..=MAX_DIGITS)
+        .flat_map(|length| {
+            (1..length)
+                .filter(move |&block_len| length.is_multiple_of(block_len))
+                .flat_map(move |block_len| block_repeats(range, block_len, length / block_len))
+        })
+        .collect();
+    ids.sort_unstable();
+    ids.dedup();
+    ids
+}
+
+/// Same result as [`solve_part2`], using [`find_invalid_ids_in_range_part2_fast`].
+/// Ranges that fail to parse are skipped.
+pub fn solve_part2_fast(input: &str) -> u64 {
+    valid_ranges(input)
+        .map(|range| {
+            find_invalid_ids_in_range_part2_fast(&range)
+                .iter()
+                .sum::<u64>()
+        })
+        .sum()
+}
+
+/// Lazily yields the palindromes of `range` with at least two digits, in
+/// ascending order, by mirroring the first half of each digit length instead of
+/// scanning the range.
+pub fn iter_palindromes_in_range(range: &Range) -> impl DoubleEndedIterator<Item = u64> + use<> {
+    let range = *range;
+    (2..=MAX_DIGITS)
+        .filter_map(move |length| {
+            clamp_to_digit_length(&range, length).map(|(lo, hi)| (length, lo, hi))
+        })
+        .flat_map(|(length, lo, hi)| {
+            // Mirroring keeps the order of the halves, so only the palindromes
+            // of the first and last half can fall outside `[lo, hi]`.
+            let shift = 10u128.pow(length / 2);
+            (lo / shift..=hi / shift)
+                .map(move |half| mirror(half, length))
+                .filter(move |palindrome| (lo..=hi).contains(palindrome))
+                .map(|palindrome| palindrome as u64)
+        })
+}
+
+/// Same result as scanning `range` with [`is_invalid_id_palindrome`].
+pub fn find_palindromes_in_range(range: &Range) -> Vec<u64> {
+    iter_palindromes_in_range(range).collect()
+}
+
+/// Number of palindromes in `range` with at least two digits, computed per
+/// digit length from the range bounds.
+pub fn count_palindromes_in_range(range: &Range) -> u64 {
+    (2..=MAX_DIGITS)
+        .filter_map(|length| clamp_to_digit_length(range, length).map(|(lo, hi)| (length, lo, hi)))
+        .map(|(length, lo, hi)| {
+            let shift = 10u128.pow(length / 2);
+            let (first, last) = (lo / shift, hi / shift);
+            // A single palindrome cannot be both below `lo` and above `hi`.
+            let below = u128::from(mirror(first, length) < lo);
+            let above = u128::from(mirror(last, length) > hi);
+            (last - first + 1 - below - above) as u64
+        })
+        .sum()
+}
+
+/// Sums the palindromes of every range, as the bonus rule asks. Ranges that
+/// fail to parse are skipped.
+pub fn solve_palindrome(input: &str) -> u64 {
+    valid_ranges(input)
+        .flat_map(|range| iter_palindromes_in_range(&range))
+        .sum()
+}
+
+/// Writes the `length`-digit palindrome whose first `length.div_ceil(2)`
+/// digits are `half`.
+fn mirror(half: u128, length: u32) -> u128 {
+    let mut palindrome = half;
+    let mut rest = if length % 2 == 1 { half / 10 } else { half };
+    while rest > 0 {
+        palindrome = palindrome * 10 + rest % 10;
+        rest /= 10;
+    }
+    palindrome
+}
+
+/// Yields, in ascending order, the IDs of `range` formed by a `block_len`-digit
+/// block (no leading zero) written `repetitions` times.
+fn block_repeats(range: &Range, block_len: u32, repetitions: u32) -> impl Iterator<Item = u64> {
+    let multiplier = repeat_multiplier(block_len, repetitions);
+    let first = 10u128
+        .pow(block_len - 1)
+        .max((range.start as u128).div_ceil(multiplier));
+    let last = (10u128.pow(block_len) - 1).min(range.end as u128 / multiplier);
+    (first..=last).map(move |block| (block * multiplier) as u64)
+}
+
+/// Number of Part 1 invalid IDs in `range`, computed from its bounds alone.
+pub fn count_half_repeats_in_range(range: &Range) -> u64 {
+    count_and_sum_half_repeats(range).0 as u64
+}
+
+/// Sum of the Part 1 invalid IDs in `range`, computed from its bounds alone.
+/// Returned as a `u128`: over wide ranges the sum does not fit in a `u64`.
+pub fn sum_half_repeats_in_range(range: &Range) -> u128 {
+    count_and_sum_half_repeats(range).1
+}
+
+/// Every even digit length contributes the halves `h` with `h * (10^k + 1)`
+/// inside the range, an arithmetic series counted and summed directly.
+fn count_and_sum_half_repeats(range: &Range) -> (u128, u128) {
+    (1..=MAX_DIGITS / 2)
+        .filter_map(|half_len| {
+            clamp_to_digit_length(range, 2 * half_len).map(|(lo, hi)| (half_len, lo, hi))
+        })
+        .map(|(half_len, lo, hi)| count_and_sum_block_repeats(half_len, 2, lo, hi))
+        .fold((0, 0), |(count, sum), (c, s)| (count + c, sum + s))
+}
+
+/// Counts and sums the Part 2 invalid IDs of a range without scanning it.
+///
+/// For each digit length `L`, an invalid ID is a block of `L / p` digits repeated
+/// `p` times for some prime `p` dividing `L`. Those sets overlap (1111 is both
+/// "1" x 4 and "11" x 2), so they are combined by inclusion-exclusion over the
+/// primes of `L`: the intersection of two such sets is again a repeated-block set
+/// whose block length is the gcd of the two.
+///
+/// # Panics
+/// Panics if the sum does not fit in a `u64`, which takes a range reaching
+/// far into the 20-digit IDs. The count always fits.
+pub fn count_and_sum_invalid_part2(range: &Range) -> (u64, u64) {
+    let (count, sum) = (1..=MAX_DIGITS)
+        .filter_map(|length| clamp_to_digit_length(range, length).map(|(lo, hi)| (length, lo, hi)))
+        .map(|(length, lo, hi)| count_and_sum_repeated(length, lo, hi))
+        .fold((0, 0), |(count, sum), (c, s)| (count + c, sum + s));
+
+    (
+        u64::try_from(count).expect("count of invalid IDs fits in u64"),
+        u64::try_from(sum).expect("sum of invalid IDs overflows u64"),
+    )
+}
+
+/// Which invalid-ID rule a [`RangeSetSolver`] is prepared for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rule {
+    /// Part 1: a block repeated exactly twice.
+    HalfRepeat,
+    /// Part 2: a block repeated at least twice.
+    AnyRepeat,
+}
+
+/// Number of IDs accepted by `validator` per digit length over all input
+/// ranges, indexed by length (0 counts as one digit, so index 0 is always 0).
+/// An arbitrary validator has to be asked about every ID; [`histograms`]
+/// counts the two puzzle rules in closed form instead.
+pub fn digit_length_histogram<F>(input: &str, validator: F) -> Result<[u64; 21], RangeParseError>
+where
+    F: Fn(u64) -> bool + Copy,
+{
+    let ranges = parse_ranges(input).collect::<Result<Vec<_>, _>>()?;
+    let mut histogram = [0; MAX_DIGITS as usize + 1];
+    for id in ranges
+        .iter()
+        .flat_map(|range| iter_ids_in_range(range, validator))
+    {
+        let length = id.checked_ilog10().map_or(1, |log| log + 1);
+        histogram[length as usize] += 1;
+    }
+    Ok(histogram)
+}
+
+/// The Part 1 and Part 2 [`digit_length_histogram`]s, parsing the input once
+/// and counting each bucket in closed form, without scanning.
+pub fn histograms(input: &str) -> Result<([u64; 21], [u64; 21]), RangeParseError> {
+    let ranges = parse_ranges(input).collect::<Result<Vec<_>, _>>()?;
+    Ok((
+        histogram_of(&ranges, Rule::HalfRepeat),
+        histogram_of(&ranges, Rule::AnyRepeat),
+    ))
+}
+
+fn histogram_of(ranges: &[Range], rule: Rule) -> [u64; 21] {
+    let mut histogram = [0; MAX_DIGITS as usize + 1];
+    for range in ranges {
+        for length in 1..=MAX_DIGITS {
+            let Some((lo, hi)) = clamp_to_digit_length(range, length) else {
+                continue;
+            };
+            let count = match rule {
+                Rule::HalfRepeat if length.is_multiple_of(2) => {
+                    count_and_sum_block_repeats(length / 2, 2, lo, hi).0
+                }
+                Rule::HalfRepeat => 0,
+                Rule::AnyRepeat => count_and_sum_repeated(length, lo, hi).0 as u128,
+            };
+            histogram[length as usize] += count as u64;
+        }
+    }
+    histogram
+}
+
+/// A query handed to a [`RangeSetSolver`] that reaches outside the ranges it
+/// was prepared for, so its answer would be incomplete.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UncoveredRangeError {
+    pub range: Range,
+}
+
+impl fmt::Display for UncoveredRangeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "range {} is not covered by the prepared ranges",
+            self.range
+        )
+    }
+}
+
+impl Error for UncoveredRangeError {}
+
+/// Answers per-range questions for many overlapping ranges at once.
+///
+/// The invalid IDs of the union of the ranges are generated once, with the
+/// constructive enumerators, and kept sorted next to their prefix sums, so
+/// counting or summing the IDs of any range inside that union is two binary
+/// searches instead of a scan.
+#[derive(Debug, Clone)]
+pub struct RangeSetSolver {
+    ranges: Vec<Range>,
+    union: Vec<Range>,
+    ids: Vec<u64>,
+    /// `prefix_sums[i]` is the sum of `ids[..i]`.
+    prefix_sums: Vec<u128>,
+}
+
+impl RangeSetSolver {
+    /// Prepares the invalid IDs under `rule` for the union of `ranges`.
+    pub fn new(ranges: &[Range], rule: Rule) -> Self {
+        let union = normalize_ranges(ranges);
+        // The union is sorted and disjoint and each enumerator yields ascending
+        // IDs, so the concatenation is sorted as well.
+        let ids: Vec<u64> = union
+            .iter()
+            .flat_map(|range| match rule {
+                Rule::HalfRepeat => find_invalid_ids_in_range_fast(range),
+                Rule::AnyRepeat => find_invalid_ids_in_range_part2_fast(range),
+            })
+            .collect();
+        let prefix_sums = std::iter::once(0)
+            .chain(ids.iter().scan(0u128, |sum, &id| {
+                *sum += id as u128;
+                Some(*sum)
+            }))
+            .collect();
+
+        RangeSetSolver {
+            ranges: ranges.to_vec(),
+            union,
+            ids,
+            prefix_sums,
+        }
+    }
+
+    /// Parses `input` and prepares a solver for all of its ranges. Every range
+    /// must parse; the first bad one is reported.
+    pub fn from_input(input: &str, rule: Rule) -> Result<Self, RangeParseError> {
+        let ranges = parse_ranges(input).collect::<Result<Vec<_>, _>>()?;
+        Ok(RangeSetSolver::new(&ranges, rule))
+    }
+
+    /// Number of invalid IDs in `range`, which must lie inside the prepared ranges.
+    pub fn count_for_range(&self, range: &Range) -> Result<u64, UncoveredRangeError> {
+        let (first, last) = self.id_span(range)?;
+        Ok((last - first) as u64)
+    }
+
+    /// Sum of the invalid IDs in `range`, which must lie inside the prepared ranges.
+    pub fn sum_for_range(&self, range: &Range) -> Result<u64, UncoveredRangeError> {
+        let (first, last) = self.id_span(range)?;
+        let sum = self.prefix_sums[last] - self.prefix_sums[first];
+        Ok(u64::try_from(sum).expect("sum of invalid IDs overflows u64"))
+    }
+
+    /// Each prepared range with the sum of its invalid IDs, in input order, as
+    /// [`solve_breakdown`] would report it.
+    pub fn breakdown(&self) -> Vec<(Range, u64)> {
+        self.ranges
+            .iter()
+            .map(|range| (*range, self.sum_for_prepared(range)))
+            .collect()
+    }
+
+    /// Sum over the prepared ranges of their invalid IDs, counting an ID once
+    /// per range containing it, as [`solve_with_validator`] does.
+    pub fn solve(&self) -> u64 {
+        self.ranges
+            .iter()
+            .map(|range| self.sum_for_prepared(range))
+            .sum()
+    }
+
+    fn sum_for_prepared(&self, range: &Range) -> u64 {
+        self.sum_for_range(range)
+            .expect("prepared ranges are covered by their union")
+    }
+
+    /// Positions in `ids` of the first invalid ID of `range` and one past its
+    /// last. An empty range holds no IDs wherever it lies.
+    fn id_span(&self, range: &Range) -> Result<(usize, usize), UncoveredRangeError> {
+        if range.is_empty() {
+            return Ok((0, 0));
+        }
+        // Merged ranges never touch, so a covered range lies inside exactly one.
+        let containing = self
+            .union
+            .partition_point(|merged| merged.start <= range.start)
+            .checked_sub(1)
+            .map(|index| self.union[index]);
+        if containing.is_none_or(|merged| range.end > merged.end) {
+            return Err(UncoveredRangeError { range: *range });
+        }
+
+        let first = self.ids.partition_point(|&id| id < range.start);
+        let last = self.ids.partition_point(|&id| id <= range.end);
+        Ok((first, last))
+    }
+}
+
+/// Restricts a range to the IDs having exactly `length` digits.
+fn clamp_to_digit_length(range: &Range, length: u32) -> Option<(u128, u128)> {
+    let lo = (range.start as u128).max(10u128.pow(length - 1));
+    let hi = (range.end as u128).min(10u128.pow(length) - 1);
+    (lo <= hi).then_some((lo, hi))
+}
+
+/// Counts and sums the `length`-digit IDs in `[lo, hi]` made of a repeated block.
+fn count_and_sum_repeated(length: u32, lo: u128, hi: u128) -> (i128, i128) {
+    let primes = distinct_prime_factors(length);
+    let mut count = 0;
+    let mut sum = 0;
+
+    for subset in 1..(1u32 << primes.len()) {
+        let repetitions: u32 = primes
+            .iter()
+            .enumerate()
+            .filter(|(bit, _)| subset & (1 << bit) != 0)
+            .map(|(_, prime)| prime)
+            .product();
+        let sign = if subset.count_ones() % 2 == 1 { 1 } else { -1 };
+        let (c, s) = count_and_sum_block_repeats(length / repetitions, repetitions, lo, hi);
+        count += sign * c as i128;
+        sum += sign * s as i128;
+    }
+
+    (count, sum)
+}
+
+/// Counts and sums the IDs in `[lo, hi]` formed by a `block_len`-digit block
+/// (no leading zero) written `repetitions` times.
+fn count_and_sum_block_repeats(
+    block_len: u32,
+    repetitions: u32,
+    lo: u128,
+    hi: u128,
+) -> (u128, u128) {
+    let multiplier = repeat_multiplier(block_len, repetitions);
+    let first = 10u128.pow(block_len - 1).max(lo.div_ceil(multiplier));
+    let last = (10u128.pow(block_len) - 1).min(hi / multiplier);
+    if first > last {
+        return (0, 0);
+    }
+
+    let count = last - first + 1;
+    let block_sum = (first + last) * count / 2;
+    (count, block_sum * multiplier)
+}
+
+/// Returns `1 + 10^k + 10^2k + ...` (`repetitions` terms), so that
+/// `block * multiplier` writes `block` out `repetitions` times.
+fn repeat_multiplier(block_len: u32, repetitions: u32) -> u128 {
+    (0..repetitions).map(|i| 10u128.pow(block_len * i)).sum()
+}
+
+fn distinct_prime_factors(mut n: u32) -> Vec<u32> {
+    let mut primes = Vec::new();
+    let mut candidate = 2;
+    while candidate * candidate <= n {
+        if n.is_multiple_of(candidate) {
+            primes.push(candidate);
+            while n.is_multiple_of(candidate) {
+                n /= candidate;
+            }
+        }
+        candidate += 1;
+    }
+    if n > 1 {
+        primes.push(n);
+    }
+    primes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "11-22,95-115,998-1012,1188511880-1188511890,222220-222224,1698522-1698528,446443-446449,38593856-38593862,565653-565659,824824821-824824827,2121212118-2121212124";
+
+    #[test]
+    fn detects_simple_invalid_id() {
+        assert!(is_invalid_id(55));
+    }
+
+    #[test]
+    fn detects_four_digit_invalid_id() {
+        assert!(is_invalid_id(6464));
+    }
+
+    #[test]
+    fn detects_six_digit_invalid_id() {
+        assert!(is_invalid_id(123123));
+    }
+
+    #[test]
+    fn valid_id_is_not_invalid() {
+        assert!(!is_invalid_id(101));
+    }
+
+    #[test]
+    fn parses_simple_range() {
+        let range = parse_range("11-22").unwrap();
+        assert_eq!(range.start, 11);
+        assert_eq!(range.end, 22);
+    }
+
+    #[test]
+    fn rejects_reversed_range() {
+        assert_eq!(
+            parse_range("22-11").err(),
+            Some("start greater than end".to_string())
+        );
+    }
+
+    #[test]
+    fn parses_single_element_range() {
+        let range = parse_range("5-5").unwrap();
+        assert_eq!((range.start, range.end), (5, 5));
+        assert_eq!(
+            find_invalid_ids_in_range(&parse_range("55-55").unwrap()),
+            vec![55]
+        );
+    }
+
+    #[test]
+    fn solve_skips_reversed_ranges() {
+        assert_eq!(solve("22-11,95-115"), 99);
+    }
+
+    #[test]
+    fn finds_invalid_ids_in_range() {
+        let range = Range { start: 11, end: 22 };
+        let invalid_ids = find_invalid_ids_in_range(&range);
+        assert_eq!(invalid_ids, vec![11, 22]);
+    }
+
+    #[test]
+    fn finds_99_in_range_95_to_115() {
+        let range = Range {
+            start: 95,
+            end: 115,
+        };
+        let invalid_ids = find_invalid_ids_in_range(&range);
+        assert_eq!(invalid_ids, vec![99]);
+    }
+
+    #[test]
+    fn finds_1010_in_range_998_to_1012() {
+        let range = Range {
+            start: 998,
+            end: 1012,
+        };
+        let invalid_ids = find_invalid_ids_in_range(&range);
+        assert_eq!(invalid_ids, vec![1010]);
+    }
+
+    #[test]
+    fn finds_no_invalid_ids_in_range_1698522_to_1698528() {
+        let range = Range {
+            start: 1698522,
+            end: 1698528,
+        };
+        let invalid_ids = find_invalid_ids_in_range(&range);
+        assert_eq!(invalid_ids, Vec::<u64>::new());
+    }
+
+    #[test]
+    fn solves_example() {
+        let input = EXAMPLE;
+        assert_eq!(solve(input), 1227775554);
+    }
+
+    #[test]
+    fn solves_part1() {
+        let input = include_str!("invalid-ids.txt");
+        assert_eq!(solve(input), 44487518055);
+    }
+
+    // Part 2 tests
+    #[test]
+    fn part2_detects_triple_digit() {
+        assert!(is_invalid_id_part2(111)); // 1 repeated 3 times
+    }
+
+    #[test]
+    fn part2_detects_quadruple_digit() {
+        assert!(is_invalid_id_part2(9999)); // 9 repeated 4 times
+    }
+
+    #[test]
+    fn part2_detects_double_pattern() {
+        assert!(is_invalid_id_part2(123123)); // 123 repeated 2 times (same as Part 1)
+    }
+
+    #[test]
+    fn part2_detects_triple_pattern() {
+        assert!(is_invalid_id_part2(123123123)); // 123 repeated 3 times
+    }
+
+    #[test]
+    fn part2_finds_99_and_111_in_range_95_to_115() {
+        let range = Range {
+            start: 95,
+            end: 115,
+        };
+        let invalid_ids = find_invalid_ids_in_range_part2(&range);
+        assert_eq!(invalid_ids, vec![99, 111]);
+    }
+
+    #[test]
+    fn part2_solves_example() {
+        let input = EXAMPLE;
+        assert_eq!(solve_part2(input), 4174379265);
+    }
+
+    #[test]
+    fn solves_part2() {
+        let input = include_str!("invalid-ids.txt");
+        assert_eq!(solve_part2(input), 53481866137);
+    }
+
+    // Refactoring tests
+    #[test]
+    fn solve_with_validator_works_with_part1_validator() {
+        let input = "11-22,95-115";
+        let result = solve_with_validator(input, is_invalid_id);
+        assert_eq!(result, 132); // (11 + 22) + 99
+    }
+
+    #[test]
+    fn solve_with_validator_accepts_closures_that_are_not_sync() {
+        // A `Cell` is not `Sync`; the sequential solver must not require it,
+        // whichever features are enabled
+        let threshold = std::cell::Cell::new(100);
+        let validator = |id| is_invalid_id(id) && id < threshold.get();
+        assert_eq!(
+            solve_with_validator("11-22,95-115", validator),
+            11 + 22 + 99
+        );
+    }
+
+    #[test]
+    fn solve_with_validator_works_with_part2_validator() {
+        let input = "11-22,95-115";
+        let result = solve_with_validator(input, is_invalid_id_part2);
+        assert_eq!(result, 243); // (11 + 22) + (99 + 111)
+    }
+
+    #[test]
+    fn find_ids_in_range_works_with_part1_validator() {
+        let range = Range {
+            start: 95,
+            end: 115,
+        };
+        let invalid_ids = find_ids_in_range(&range, is_invalid_id);
+        assert_eq!(invalid_ids, vec![99]);
+    }
+
+    #[test]
+    fn find_ids_in_range_works_with_part2_validator() {
+        let range = Range {
+            start: 95,
+            end: 115,
+        };
+        let invalid_ids = find_ids_in_range(&range, is_invalid_id_part2);
+        assert_eq!(invalid_ids, vec![99, 111]);
+    }
+
+    #[test]
+    fn count_and_sum_invalid_part2_matches_brute_force_on_95_to_115() {
+        let range = Range {
+            start: 95,
+            end: 115,
+        };
+        let invalid_ids = find_invalid_ids_in_range_part2(&range);
+        assert_eq!(
+            count_and_sum_invalid_part2(&range),
+            (invalid_ids.len() as u64, invalid_ids.iter().sum())
+        );
+    }
+
+    #[test]
+    fn count_and_sum_invalid_part2_counts_multiply_generated_ids_once() {
+        // 1111 is "1" x 4 and "11" x 2; 111111 is "1" x 6, "11" x 3 and "111" x 2
+        let range = Range {
+            start: 1000,
+            end: 999999,
+        };
+        let invalid_ids = find_invalid_ids_in_range_part2(&range);
+        assert_eq!(
+            count_and_sum_invalid_part2(&range),
+            (invalid_ids.len() as u64, invalid_ids.iter().sum())
+        );
+    }
+
+    #[test]
+    fn count_and_sum_invalid_part2_matches_example_total() {
+        let input = EXAMPLE;
+        let total: u64 = input
+            .split(',')
+            .map(|range| count_and_sum_invalid_part2(&parse_range(range).unwrap()).1)
+            .sum();
+        assert_eq!(total, 4174379265);
+    }
+
+    #[test]
+    fn count_and_sum_invalid_part2_handles_huge_ranges() {
+        let range = Range {
+            start: 1,
+            end: 10_000_000_000,
+        };
+        // Every block of 1 to 5 digits repeated to fill 2 to 10 digits,
+        // each ID counted once
+        assert_eq!(
+            count_and_sum_invalid_part2(&range),
+            (101_088, 496_436_544_631_305)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "sum of invalid IDs overflows u64")]
+    fn count_and_sum_invalid_part2_panics_when_the_sum_overflows() {
+        count_and_sum_invalid_part2(&Range {
+            start: 1,
+            end: u64::MAX,
+        });
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn invalid_ids_yields_first_match_lazily() {
+        let range = Range {
+            start: 95,
+            end: 115,
+        };
+        assert_eq!(invalid_ids(&range, is_invalid_id).next(), Some(99));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn invalid_ids_stops_early_on_huge_range() {
+        let range = Range {
+            start: 1,
+            end: u64::MAX,
+        };
+        let first: Vec<u64> = invalid_ids(&range, is_invalid_id).take(3).collect();
+        assert_eq!(first, vec![11, 22, 33]);
+    }
+
+    // Radix tests
+    #[test]
+    fn radix_detects_hex_half_repeat() {
+        assert!(is_invalid_id_in_base(0xABAB, 16));
+        assert!(is_invalid_id_part2_in_base(0xABAB, 16));
+        assert!(is_invalid_id_part2_in_base(0xAAA, 16));
+        assert!(!is_invalid_id_in_base(0xAAA, 16));
+        assert!(!is_invalid_id_in_base(0xABBA, 16));
+    }
+
+    #[test]
+    fn radix_10_matches_decimal_validators() {
+        for id in 0..200_000 {
+            assert_eq!(is_invalid_id_in_base(id, 10), is_invalid_id(id), "{}", id);
+            assert_eq!(
+                is_invalid_id_part2_in_base(id, 10),
+                is_invalid_id_part2(id),
+                "{}",
+                id
+            );
+        }
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn deprecated_radix_names_still_answer_like_in_base() {
+        for id in [0xABAB, 0xAAA, 0xABBA, 0b1010, 1212] {
+            for base in [2, 10, 16] {
+                assert_eq!(
+                    is_invalid_id_radix(id, base),
+                    is_invalid_id_in_base(id, base)
+                );
+                assert_eq!(
+                    is_invalid_id_part2_radix(id, base),
+                    is_invalid_id_part2_in_base(id, base)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn radix_2_uses_binary_digits() {
+        assert!(is_invalid_id_in_base(0b1010, 2));
+        assert!(is_invalid_id_part2_in_base(0b111, 2));
+        assert!(!is_invalid_id_in_base(0b1001, 2));
+    }
+
+    #[test]
+    #[should_panic(expected = "radix must be in 2..=36")]
+    fn radix_out_of_range_panics() {
+        is_invalid_id_in_base(11, 37);
+    }
+
+    #[test]
+    fn repeating_pattern_finds_block_and_count() {
+        assert_eq!(repeating_pattern(123123), Some(("123".to_string(), 2)));
+        assert_eq!(repeating_pattern(123123123), Some(("123".to_string(), 3)));
+    }
+
+    #[test]
+    fn repeating_pattern_prefers_shortest_block() {
+        assert_eq!(repeating_pattern(1111), Some(("1".to_string(), 4)));
+    }
+
+    #[test]
+    fn repeating_pattern_is_none_for_non_repeating_id() {
+        assert_eq!(repeating_pattern(1234), None);
+        assert_eq!(repeating_pattern(7), None);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel_solve_matches_sequential_scan() {
+        let input = EXAMPLE;
+        let sequential: u64 = input
+            .split(',')
+            .map(|range| find_invalid_ids_in_range(&parse_range(range).unwrap()))
+            .map(|ids| ids.iter().sum::<u64>())
+            .sum();
+        assert_eq!(
+            solve_with_validator_parallel(input, is_invalid_id),
+            sequential
+        );
+        assert_eq!(sequential, 1227775554);
+        assert_eq!(
+            solve_with_validator_parallel(input, is_invalid_id_part2),
+            solve_with_validator(input, is_invalid_id_part2)
+        );
+    }
+
+    #[test]
+    fn solve_breakdown_reports_each_range() {
+        let breakdown = solve_breakdown("11-22,95-115", is_invalid_id);
+        assert_eq!(
+            breakdown,
+            vec![
+                (Range { start: 11, end: 22 }, 33),
+                (
+                    Range {
+                        start: 95,
+                        end: 115
+                    },
+                    99
+                ),
+            ]
+        );
+        let total: u64 = breakdown.iter().map(|(_, sum)| sum).sum();
+        assert_eq!(total, solve("11-22,95-115"));
+    }
+
+    #[test]
+    fn fast_invalid_ids_match_scan_on_small_ranges() {
+        for (start, end) in [
+            (1, 10),
+            (11, 22),
+            (95, 115),
+            (998, 1012),
+            (1, 100_000),
+            (123_000, 124_999),
+            (222_220, 222_224),
+            (1_188_511_880, 1_188_511_890),
+        ] {
+            let range = Range { start, end };
+            assert_eq!(
+                find_invalid_ids_in_range_fast(&range),
+                find_invalid_ids_in_range(&range),
+                "{}-{}",
+                start,
+                end
+            );
+        }
+    }
+
+    #[test]
+    fn solve_fast_matches_solve() {
+        let input = EXAMPLE;
+        assert_eq!(solve_fast(input), 1227775554);
+        assert_eq!(solve_fast(include_str!("invalid-ids.txt")), 44487518055);
+    }
+
+    #[test]
+    fn solve_fast_handles_a_trillion_ids() {
+        // Every k-digit half h (k = 1..=6) contributes h * (10^k + 1):
+        // sum over k of (10^k + 1) * (10^(k-1) + 10^k - 1) * 9 * 10^(k-1) / 2
+        assert_eq!(solve_fast("1-1000000000000"), 495495540949540950);
+    }
+
+    /// Deterministic linear congruential generator for randomized tests.
+    fn pseudo_random(seed: &mut u64) -> u64 {
+        *seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        *seed >> 33
+    }
+
+    #[test]
+    fn fast_part2_solves_example() {
+        let input = EXAMPLE;
+        assert_eq!(solve_part2_fast(input), 4174379265);
+    }
+
+    #[test]
+    fn fast_part2_matches_scan_on_random_small_ranges() {
+        let mut seed = 2025;
+        for _ in 0..500 {
+            let start = pseudo_random(&mut seed) % 1_000_000;
+            let end = start + pseudo_random(&mut seed) % 5_000;
+            let range = Range { start, end };
+            assert_eq!(
+                find_invalid_ids_in_range_part2_fast(&range),
+                find_invalid_ids_in_range_part2(&range),
+                "{}-{}",
+                start,
+                end
+            );
+        }
+    }
+
+    #[test]
+    fn fast_part2_spans_digit_length_boundary() {
+        let range = Range {
+            start: 9990,
+            end: 10010,
+        };
+        assert_eq!(find_invalid_ids_in_range_part2_fast(&range), vec![9999]);
+        assert_eq!(
+            find_invalid_ids_in_range_part2_fast(&range),
+            find_invalid_ids_in_range_part2(&range)
+        );
+    }
+
+    mod properties {
+        use super::super::*;
+        use super::pseudo_random;
+
+        /// The string-based validators the digit-buffer ones replaced.
+        fn reference_is_invalid_id(id: u64) -> bool {
+            let s = id.to_string();
+            s.len().is_multiple_of(2) && s[..s.len() / 2] == s[s.len() / 2..]
+        }
+
+        fn reference_is_invalid_id_part2(id: u64) -> bool {
+            let s = id.to_string();
+            let len = s.len();
+            (1..=len / 2).any(|k| len.is_multiple_of(k) && s[..k].repeat(len / k) == s)
+        }
+
+        fn assert_matches_reference(id: u64) {
+            assert_eq!(is_invalid_id(id), reference_is_invalid_id(id), "{}", id);
+            assert_eq!(
+                is_invalid_id_part2(id),
+                reference_is_invalid_id_part2(id),
+                "{}",
+                id
+            );
+        }
+
+        #[test]
+        fn validators_match_reference_for_first_ten_million_ids() {
+            for id in 0..10_000_000 {
+                assert_matches_reference(id);
+            }
+        }
+
+        #[test]
+        fn validators_match_reference_near_the_twenty_digit_limit() {
+            let mut seed = 20;
+            for _ in 0..100_000 {
+                let high = pseudo_random(&mut seed) << 32 | pseudo_random(&mut seed);
+                assert_matches_reference(u64::MAX - high % 10_000_000_000_000_000_000);
+            }
+            // 20-digit half repeats: the largest half that still fits in a u64
+            for half in 1_000_000_000..1_000_010_000 {
+                assert_matches_reference(half * 10_000_000_001);
+            }
+            for half in 1_844_664_407..=1_844_674_407 {
+                assert_matches_reference(half * 10_000_000_001);
+            }
+            assert_matches_reference(u64::MAX);
+        }
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn solve_parallel_matches_sequential_on_example() {
+        let input = EXAMPLE;
+        assert_eq!(solve_parallel(input), 1227775554);
+        assert_eq!(solve_part2_parallel(input), 4174379265);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn solve_parallel_chunks_a_single_range() {
+        let options = SolveOptions { chunk_size: 7 };
+        let chunks: Vec<Range> = range_chunks(Range { start: 1, end: 20 }, 7).collect();
+        assert_eq!(
+            chunks,
+            vec![
+                Range { start: 1, end: 7 },
+                Range { start: 8, end: 14 },
+                Range { start: 15, end: 20 },
+            ]
+        );
+
+        let input = "1-2000000";
+        assert_eq!(
+            solve_parallel_with(input, is_invalid_id, options),
+            solve_fast(input)
+        );
+        assert_eq!(
+            solve_parallel_with(input, is_invalid_id_part2, SolveOptions { chunk_size: 999 }),
+            solve_part2_fast(input)
+        );
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn range_chunks_stop_at_u64_max() {
+        let range = Range {
+            start: u64::MAX - 4,
+            end: u64::MAX,
+        };
+        let chunks: Vec<Range> = range_chunks(range, 3).collect();
+        assert_eq!(
+            chunks,
+            vec![
+                Range {
+                    start: u64::MAX - 4,
+                    end: u64::MAX - 2
+                },
+                Range {
+                    start: u64::MAX - 1,
+                    end: u64::MAX
+                },
+            ]
+        );
+    }
 
-    first_half == second_half
-}
+    #[test]
+    fn solve_deduplicated_counts_overlapping_ids_once() {
+        // 22 lies in both ranges
+        assert_eq!(solve_with_validator("10-30,20-40", is_invalid_id), 88);
+        assert_eq!(solve_deduplicated("10-30,20-40", is_invalid_id), Ok(66));
+    }
 
-pub fn is_invalid_id_part2(id: u64) -> bool {
-    let s = id.to_string();
-    let len = s.len();
+    #[test]
+    fn normalize_ranges_merges_adjacent_ranges() {
+        let ranges = [Range { start: 11, end: 20 }, Range { start: 1, end: 10 }];
+        assert_eq!(normalize_ranges(&ranges), vec![Range { start: 1, end: 20 }]);
+    }
 
-    // Try all possible pattern lengths from 1 to len/2
-    // Only consider lengths that divide the total string length
-    (1..=len / 2)
-        .filter(|&k| len.is_multiple_of(k))
-        .any(|k| {
-            let pattern = &s[..k];
-            let repetitions = len / k;
-            pattern.repeat(repetitions) == s
-        })
-}
+    #[test]
+    fn normalize_ranges_keeps_disjoint_ranges() {
+        let ranges = [Range { start: 30, end: 40 }, Range { start: 1, end: 10 }];
+        assert_eq!(
+            normalize_ranges(&ranges),
+            vec![Range { start: 1, end: 10 }, Range { start: 30, end: 40 }]
+        );
+    }
 
-pub struct Range {
-    pub start: u64,
-    pub end: u64,
-}
+    #[test]
+    fn solve_deduplicated_matches_solve_on_disjoint_ranges() {
+        let input = EXAMPLE;
+        assert_eq!(
+            solve_deduplicated(input, is_invalid_id),
+            Ok(solve_with_validator(input, is_invalid_id))
+        );
+        assert_eq!(
+            solve_deduplicated(input, is_invalid_id_part2),
+            Ok(solve_with_validator(input, is_invalid_id_part2))
+        );
+    }
 
-pub fn parse_range(input: &str) -> Result<Range, String> {
-    let parts: Vec<&str> = input.split('-').collect();
-    if parts.len() != 2 {
-        return Err("Invalid range format".to_string());
+    #[test]
+    fn solve_deduplicated_rejects_reversed_range() {
+        assert_eq!(
+            solve_deduplicated("11-22,40-30", is_invalid_id),
+            Err(RangeParseError {
+                index: 1,
+                text: "40-30".to_string(),
+                reason: "start greater than end".to_string()
+            })
+        );
     }
 
-    let start = parts[0]
-        .parse::<u64>()
-        .map_err(|_| "Invalid start number".to_string())?;
-    let end = parts[1]
-        .parse::<u64>()
-        .map_err(|_| "Invalid end number".to_string())?;
+    #[test]
+    fn try_solve_solves_example() {
+        let input = EXAMPLE;
+        assert_eq!(try_solve(input), Ok(1227775554));
+        assert_eq!(try_solve_part2(input), Ok(4174379265));
+    }
 
-    Ok(Range { start, end })
-}
+    #[test]
+    fn try_solve_reports_index_of_bad_segment() {
+        let error = try_solve("11-22, 95-1x5 ,998-1012").unwrap_err();
+        assert_eq!(
+            error,
+            RangeParseError {
+                index: 1,
+                text: "95-1x5".to_string(),
+                reason: "Invalid end number".to_string()
+            }
+        );
+        assert_eq!(error.to_string(), "range 1 \"95-1x5\": Invalid end number");
+    }
 
-pub fn find_ids_in_range<F>(range: &Range, validator: F) -> Vec<u64>
-where
-    F: Fn(u64) -> bool + Copy,
-{
-    (range.start..=range.end)
-        .filter(|&id| validator(id))
-        .collect()
-}
+    #[test]
+    fn try_solve_reports_empty_segment() {
+        let error = try_solve("11-22,,95-115").unwrap_err();
+        assert_eq!((error.index, error.text.as_str()), (1, ""));
+        // solve keeps skipping it
+        assert_eq!(solve("11-22,,95-115"), 132);
+    }
 
-pub fn find_invalid_ids_in_range(range: &Range) -> Vec<u64> {
-    find_ids_in_range(range, is_invalid_id)
-}
+    #[test]
+    fn try_solve_collecting_errors_reports_every_bad_segment() {
+        let errors = try_solve_collecting_errors("x,11-22,,30-20", is_invalid_id).unwrap_err();
+        let indices: Vec<usize> = errors.iter().map(|e| e.index).collect();
+        assert_eq!(indices, vec![0, 2, 3]);
+        assert_eq!(
+            try_solve_collecting_errors("11-22,95-115", is_invalid_id),
+            Ok(132)
+        );
+    }
 
-pub fn solve_with_validator<F>(input: &str, validator: F) -> u64
-where
-    F: Fn(u64) -> bool + Copy,
-{
-    let mut total = 0;
+    #[test]
+    fn iter_invalid_ids_takes_first_few_of_huge_range() {
+        let range = Range {
+            start: 1,
+            end: 1_000_000_000_000_000,
+        };
+        let first: Vec<u64> = iter_invalid_ids_part2(&range).take(3).collect();
+        assert_eq!(first, vec![11, 22, 33]);
+    }
 
-    for range_str in input.split(',') {
-        let range_str = range_str.trim();
-        if let Ok(range) = parse_range(range_str) {
-            let invalid_ids = find_ids_in_range(&range, validator);
-            total += invalid_ids.iter().sum::<u64>();
-        }
+    #[test]
+    fn iter_invalid_ids_rev_finds_largest() {
+        // 15-digit IDs cannot be a half repeated, so the answer sits just below them
+        let range = Range {
+            start: 1,
+            end: 100_000_000_000_005,
+        };
+        assert_eq!(
+            iter_invalid_ids(&range).next_back(),
+            Some(99_999_999_999_999)
+        );
+        let range = Range {
+            start: 95,
+            end: 115,
+        };
+        assert_eq!(iter_invalid_ids_part2(&range).next_back(), Some(111));
     }
 
-    total
-}
+    #[test]
+    fn iter_invalid_ids_sums_to_solve() {
+        let input = EXAMPLE;
+        let total: u64 = input
+            .split(',')
+            .map(|range| iter_invalid_ids(&parse_range(range).unwrap()).sum::<u64>())
+            .sum();
+        assert_eq!(total, solve(input));
+    }
 
-pub fn solve(input: &str) -> u64 {
-    solve_with_validator(input, is_invalid_id)
-}
+    #[test]
+    fn solve_report_breaks_down_example() {
+        let input = EXAMPLE;
+        let reports = solve_report(input, is_invalid_id_part2).unwrap();
 
-pub fn find_invalid_ids_in_range_part2(range: &Range) -> Vec<u64> {
-    find_ids_in_range(range, is_invalid_id_part2)
-}
+        assert_eq!(reports.len(), 11);
+        assert_eq!(
+            reports[1],
+            RangeReport {
+                range: Range {
+                    start: 95,
+                    end: 115
+                },
+                count: 2,
+                sum: 210,
+                min: Some(99),
+                max: Some(111),
+            }
+        );
+        assert_eq!(
+            reports[2],
+            RangeReport {
+                range: Range {
+                    start: 998,
+                    end: 1012
+                },
+                count: 2,
+                sum: 2009,
+                min: Some(999),
+                max: Some(1010),
+            }
+        );
+        let total: u128 = reports.iter().map(|report| report.sum).sum();
+        assert_eq!(total, solve_u128(input, is_invalid_id_part2));
+    }
 
-pub fn solve_part2(input: &str) -> u64 {
-    solve_with_validator(input, is_invalid_id_part2)
-}
+    #[test]
+    fn solve_report_sum_does_not_wrap_past_u64() {
+        let reports = solve_report("18446744073709551000-18446744073709551615", |_| true).unwrap();
+        let (first, last) = (18446744073709551000u128, u64::MAX as u128);
+        assert_eq!(reports[0].count, 616);
+        assert_eq!(reports[0].sum, (first + last) * 616 / 2);
+        assert!(reports[0].sum > u64::MAX as u128);
+    }
 
-#[cfg(test)]
+    #[test]
+    fn solve_report_range_without_matches_has_no_min_or_max() {
+        let reports = solve_report("1698522-1698528", is_invalid_id).unwrap();
+        assert_eq!(reports[0].count, 0);
+        assert_eq!(reports[0].sum, 0);
+        assert_eq!(reports[0].min, None);
+        assert_eq!(reports[0].max, None);
+    }
 
-mod tests {
-    use super::*;
+    #[test]
+    fn solve_report_rejects_bad_segment() {
+        assert_eq!(
+            solve_report("11-22,oops", is_invalid_id).unwrap_err().index,
+            1
+        );
+    }
 
     #[test]
-    fn detects_simple_invalid_id() {
-        assert!(is_invalid_id(55));
+    fn kmp_validator_matches_part2_for_first_ten_million_ids() {
+        for id in 1..=10_000_000 {
+            assert_eq!(
+                is_invalid_id_part2_fast(id),
+                is_invalid_id_part2(id),
+                "{}",
+                id
+            );
+        }
     }
 
     #[test]
-    fn detects_four_digit_invalid_id() {
-        assert!(is_invalid_id(6464));
+    fn kmp_validator_handles_long_ids() {
+        for id in [
+            123_456_123_456_123_456,    // 18 digits, "123456" x 3
+            121_212_121_212_121_212,    // 18 digits, "12" x 9
+            123_456_789_123_456_789,    // 18 digits, "123456789" x 2
+            1_111_111_111_111_111_111,  // 19 digits, "1" x 19
+            1_844_674_407_184_467_440,  // 19 digits, no repeat
+            18_446_744_071_844_674_407, // 20 digits, "1844674407" x 2
+            12_121_212_121_212_121_212, // 20 digits, "12" x 10
+            10_000_000_001_000_000_000, // 20 digits, no repeat
+            u64::MAX,
+        ] {
+            assert_eq!(
+                is_invalid_id_part2_fast(id),
+                is_invalid_id_part2(id),
+                "{}",
+                id
+            );
+        }
+        assert!(is_invalid_id_part2_fast(18_446_744_071_844_674_407));
+        assert!(!is_invalid_id_part2_fast(u64::MAX));
+        assert!(!is_invalid_id_part2_fast(7));
     }
 
     #[test]
-    fn detects_six_digit_invalid_id() {
-        assert!(is_invalid_id(123123));
+    fn invalid_pattern_explains_single_digit_repeat() {
+        assert_eq!(
+            invalid_pattern(7777777),
+            Some(PatternMatch {
+                pattern: 7,
+                length: 1,
+                repetitions: 7
+            })
+        );
     }
 
     #[test]
-    fn valid_id_is_not_invalid() {
-        assert!(!is_invalid_id(101));
+    fn invalid_pattern_explains_multi_digit_repeat() {
+        assert_eq!(
+            invalid_pattern(824824824),
+            Some(PatternMatch {
+                pattern: 824,
+                length: 3,
+                repetitions: 3
+            })
+        );
     }
 
     #[test]
-    fn parses_simple_range() {
-        let range = parse_range("11-22").unwrap();
-        assert_eq!(range.start, 11);
-        assert_eq!(range.end, 22);
+    fn invalid_pattern_is_none_for_valid_ids() {
+        assert_eq!(invalid_pattern(101), None);
+        assert_eq!(invalid_pattern(5), None);
+        assert_eq!(invalid_pattern(1698522), None);
     }
 
     #[test]
-    fn finds_invalid_ids_in_range() {
-        let range = Range { start: 11, end: 22 };
-        let invalid_ids = find_invalid_ids_in_range(&range);
-        assert_eq!(invalid_ids, vec![11, 22]);
+    fn invalid_pattern_prefers_shortest_period() {
+        assert_eq!(
+            invalid_pattern(1111),
+            Some(PatternMatch {
+                pattern: 1,
+                length: 1,
+                repetitions: 4
+            })
+        );
+        assert_eq!(
+            invalid_pattern(12121212),
+            Some(PatternMatch {
+                pattern: 12,
+                length: 2,
+                repetitions: 4
+            })
+        );
     }
 
     #[test]
-    fn finds_99_in_range_95_to_115() {
+    fn explain_range_lists_ids_with_patterns() {
         let range = Range {
             start: 95,
             end: 115,
         };
-        let invalid_ids = find_invalid_ids_in_range(&range);
-        assert_eq!(invalid_ids, vec![99]);
+        let explained = explain_range(&range);
+        let ids: Vec<u64> = explained.iter().map(|&(id, _)| id).collect();
+        assert_eq!(ids, find_invalid_ids_in_range_part2(&range));
+        assert_eq!(explained[1].1.pattern, 1);
+        assert_eq!(explained[1].1.repetitions, 3);
     }
 
     #[test]
-    fn finds_1010_in_range_998_to_1012() {
+    fn parses_range_with_spaces_around_dash() {
+        assert_eq!(parse_range(" 11 - 22 "), Ok(Range { start: 11, end: 22 }));
+    }
+
+    #[test]
+    fn solve_accepts_newline_separated_ranges() {
+        let input = EXAMPLE;
+        let one_per_line = input.replace(',', "\n");
+        assert_eq!(solve(&one_per_line), solve(input));
+        assert_eq!(solve_part2(&one_per_line), solve_part2(input));
+    }
+
+    #[test]
+    fn solve_accepts_mixed_separators_and_comments() {
+        let input = "# gift shop ranges\n  11 - 22 ,95-115\n\n# the rest\n998-1012\n";
+        assert_eq!(try_solve(input), Ok(11 + 22 + 99 + 1010));
+        assert_eq!(solve(input), solve("11-22,95-115,998-1012"));
+    }
+
+    #[test]
+    fn composite_of_both_rules_equals_part2() {
+        let input = EXAMPLE;
+        let mut composite = CompositeValidator::new(vec![
+            Box::new(HalfRepeatValidator::default()),
+            Box::new(AnyRepeatValidator::default()),
+        ]);
+        assert_eq!(composite.name(), "half repeat or any repeat");
+        assert_eq!(solve_with(input, &mut composite), 4174379265);
+        assert_eq!(
+            solve_with(input, &mut AnyRepeatValidator::default()),
+            solve_part2(input)
+        );
+    }
+
+    #[test]
+    fn stateful_validators_match_stateless_ones() {
+        let mut half = HalfRepeatValidator::default();
+        let mut any = AnyRepeatValidator::default();
+        for id in (0..200_000).chain(u64::MAX - 1000..=u64::MAX) {
+            assert_eq!(half.is_invalid(id), is_invalid_id(id), "{}", id);
+            assert_eq!(any.is_invalid(id), is_invalid_id_part2(id), "{}", id);
+        }
+    }
+
+    #[test]
+    fn closures_are_validators() {
+        let mut calls = 0;
+        let mut counting = |id| {
+            calls += 1;
+            is_invalid_id(id)
+        };
+        assert_eq!(solve_with("11-22,95-115", &mut counting), 132);
+        assert_eq!(calls, 12 + 21);
+        assert_eq!(solve_with("11-22", &mut is_invalid_id), 33);
+    }
+
+    #[test]
+    fn solve_checked_reports_overflow_that_u128_sums_exactly() {
+        // Each range holds the single invalid ID 9999999999999999999
+        let input = "9999999999999999998-9999999999999999999,".repeat(3);
+        let input = input.trim_end_matches(',');
+
+        assert_eq!(
+            solve_checked(input, is_invalid_id_part2),
+            Err(SolveError::Overflow {
+                range_index: 1,
+                partial_sum: 9_999_999_999_999_999_999
+            })
+        );
+        assert_eq!(
+            solve_u128(input, is_invalid_id_part2),
+            3 * 9_999_999_999_999_999_999u128
+        );
+    }
+
+    #[test]
+    fn solve_checked_matches_solve_without_overflow() {
+        let input = EXAMPLE;
+        assert_eq!(solve_checked(input, is_invalid_id), Ok(1227775554));
+        assert_eq!(solve_u128(input, is_invalid_id_part2), 4174379265);
+        assert!(matches!(
+            solve_checked("11-22,x", is_invalid_id),
+            Err(SolveError::Parse(RangeParseError { index: 1, .. }))
+        ));
+    }
+
+    #[test]
+    fn single_element_range_has_length_one() {
+        let range: Range = "55-55".parse().unwrap();
+        assert_eq!(range.len(), 1);
+        assert!(!range.is_empty());
+        assert!(range.contains(55));
+        assert!(!range.contains(54));
+        assert_eq!(range.iter().collect::<Vec<_>>(), vec![55]);
+    }
+
+    #[test]
+    fn full_u64_range_length_does_not_overflow() {
         let range = Range {
-            start: 998,
-            end: 1012,
+            start: 0,
+            end: u64::MAX,
         };
-        let invalid_ids = find_invalid_ids_in_range(&range);
-        assert_eq!(invalid_ids, vec![1010]);
+        assert_eq!(range.len(), u128::from(u64::MAX) + 1);
+        assert!(range.contains(u64::MAX));
+        assert_eq!(range.to_string().parse::<Range>(), Ok(range));
     }
 
     #[test]
-    fn finds_no_invalid_ids_in_range_1698522_to_1698528() {
+    fn reversed_range_is_empty() {
+        let range = Range { start: 22, end: 11 };
+        assert!(range.is_empty());
+        assert_eq!(range.len(), 0);
+        assert_eq!(range.iter().count(), 0);
+    }
+
+    #[test]
+    fn intersect_overlapping_disjoint_and_nested_ranges() {
+        let range = Range { start: 10, end: 30 };
+        assert_eq!(
+            range.intersect(&Range { start: 20, end: 40 }),
+            Some(Range { start: 20, end: 30 })
+        );
+        assert_eq!(range.intersect(&Range { start: 31, end: 40 }), None);
+        assert_eq!(
+            range.intersect(&Range { start: 15, end: 16 }),
+            Some(Range { start: 15, end: 16 })
+        );
+        assert_eq!(
+            range.intersect(&Range { start: 30, end: 30 }),
+            Some(Range { start: 30, end: 30 })
+        );
+    }
+
+    #[test]
+    fn range_round_trips_through_display() {
+        let range: Range = "11-22".parse().unwrap();
+        assert_eq!(range.to_string(), "11-22");
+        assert_eq!(parse_range("11-22"), Ok(range));
+    }
+
+    #[test]
+    fn part1_histogram_of_example() {
+        let input = EXAMPLE;
+        let histogram = digit_length_histogram(input, is_invalid_id).unwrap();
+
+        // 11, 22, 99 | 1010 | 222222, 446446 | 38593859 | 1188511885
+        let mut expected = [0; 21];
+        expected[2] = 3;
+        expected[4] = 1;
+        expected[6] = 2;
+        expected[8] = 1;
+        expected[10] = 1;
+        assert_eq!(histogram, expected);
+
+        let scanned: usize = input
+            .split(',')
+            .map(|range| find_invalid_ids_in_range(&parse_range(range).unwrap()).len())
+            .sum();
+        assert_eq!(histogram.iter().sum::<u64>(), scanned as u64);
+    }
+
+    #[test]
+    fn histograms_cover_both_rules() {
+        let input = "95-115,998-1012,1000-999999";
+        let (part1, part2) = histograms(input).unwrap();
+        assert_eq!(part1, digit_length_histogram(input, is_invalid_id).unwrap());
+        assert_eq!(
+            part2,
+            digit_length_histogram(input, is_invalid_id_part2).unwrap()
+        );
+
         let range = Range {
-            start: 1698522,
-            end: 1698528,
+            start: 1000,
+            end: 999999,
         };
-        let invalid_ids = find_invalid_ids_in_range(&range);
-        assert_eq!(invalid_ids, Vec::<u64>::new());
+        let scanned = find_invalid_ids_in_range_part2(&range);
+        let six_digit = scanned.iter().filter(|&&id| id >= 100000).count() as u64;
+        assert_eq!(part2[6], six_digit);
+        assert_eq!(part2[3], 2); // 99 is 2 digits; 111 and 999 are 3
+        assert_eq!(part2[2], 1);
     }
 
     #[test]
-    fn solves_example() {
-        let input = "11-22,95-115,998-1012,1188511880-1188511890,222220-222224,1698522-1698528,446443-446449,38593856-38593862,565653-565659,824824821-824824827,2121212118-2121212124";
-        assert_eq!(solve(input), 1227775554);
+    fn histogram_takes_any_validator() {
+        let histogram = digit_length_histogram("0-20", |id| id.is_multiple_of(2)).unwrap();
+
+        // 0, 2, 4, 6, 8 | 10, 12, 14, 16, 18, 20
+        let mut expected = [0; 21];
+        expected[1] = 5;
+        expected[2] = 6;
+        assert_eq!(histogram, expected);
     }
 
     #[test]
-    fn solves_part1() {
-        let input = include_str!("invalid-ids.txt");
-        assert_eq!(solve(input), 44487518055);
+    fn histogram_rejects_bad_segment() {
+        assert_eq!(
+            digit_length_histogram("11-22,zz", is_invalid_id_part2)
+                .unwrap_err()
+                .index,
+            1
+        );
     }
 
-    // Part 2 tests
     #[test]
-    fn part2_detects_triple_digit() {
-        assert!(is_invalid_id_part2(111)); // 1 repeated 3 times
+    fn base_10_reproduces_example_answers() {
+        let input = EXAMPLE;
+        assert_eq!(solve_in_base(input, 10), 1227775554);
+        assert_eq!(solve_part2_in_base(input, 10), 4174379265);
     }
 
     #[test]
-    fn part2_detects_quadruple_digit() {
-        assert!(is_invalid_id_part2(9999)); // 9 repeated 4 times
+    fn base_2_matches_binary_strings() {
+        for id in 0..100_000u64 {
+            let binary = format!("{:b}", id);
+            let (first, second) = binary.split_at(binary.len() / 2);
+            assert_eq!(
+                is_invalid_id_in_base(id, 2),
+                binary.len().is_multiple_of(2) && first == second,
+                "{}",
+                binary
+            );
+        }
+        // 0b11 and 0b1111 repeat, 0b1001 and the odd-length 0b101 do not
+        assert!(is_invalid_id_in_base(0b11, 2));
+        assert!(is_invalid_id_in_base(0b1111, 2));
+        assert!(!is_invalid_id_in_base(0b1001, 2));
+        assert!(is_invalid_id_part2_in_base(0b111, 2));
+        assert!(!is_invalid_id_in_base(0b101, 2));
     }
 
     #[test]
-    fn part2_detects_double_pattern() {
-        assert!(is_invalid_id_part2(123123)); // 123 repeated 2 times (same as Part 1)
+    fn leading_digits_are_taken_per_base() {
+        // 0x1010 is a hex repeat but 4112 in decimal is not; 1010 is the reverse
+        assert!(is_invalid_id_in_base(0x1010, 16));
+        assert!(!is_invalid_id_in_base(0x1010, 10));
+        assert!(is_invalid_id_in_base(1010, 10));
+        assert!(!is_invalid_id_in_base(1010, 16));
+        // 5 is "101" in base 2: padded to "0101" it would wrongly look repeated
+        assert!(!is_invalid_id_in_base(5, 2));
+        assert!(!is_invalid_id_part2_in_base(5, 2));
+        assert!(is_invalid_id_part2_in_base(35 * 36 + 35, 36));
     }
 
     #[test]
-    fn part2_detects_triple_pattern() {
-        assert!(is_invalid_id_part2(123123123)); // 123 repeated 3 times
+    fn range_set_solver_matches_brute_force_on_overlapping_windows() {
+        // 1000 windows of 20_000 IDs, each overlapping its neighbours by half
+        let windows: Vec<Range> = (0..1000u64)
+            .map(|i| {
+                let start = 1 + i * 9_990;
+                Range {
+                    start,
+                    end: (start + 19_999).min(10_000_000),
+                }
+            })
+            .collect();
+        assert_eq!(windows.last().unwrap().end, 10_000_000);
+
+        let part1 = RangeSetSolver::new(&windows, Rule::HalfRepeat);
+        let part2 = RangeSetSolver::new(&windows, Rule::AnyRepeat);
+        for window in &windows {
+            let ids: Vec<u64> = window
+                .iter()
+                .filter(|&id| is_invalid_id_part2(id))
+                .collect();
+            assert_eq!(part2.count_for_range(window), Ok(ids.len() as u64));
+            assert_eq!(part2.sum_for_range(window), Ok(ids.iter().sum()));
+
+            let (count, sum) = window
+                .iter()
+                .filter(|&id| is_invalid_id(id))
+                .fold((0, 0), |(count, sum), id| (count + 1, sum + id));
+            assert_eq!(part1.count_for_range(window), Ok(count));
+            assert_eq!(part1.sum_for_range(window), Ok(sum));
+        }
     }
 
     #[test]
-    fn part2_finds_99_and_111_in_range_95_to_115() {
+    fn range_set_solver_agrees_with_the_naive_solvers() {
+        let input = &format!("{EXAMPLE},10-120,1000-1200");
+
+        let part1 = RangeSetSolver::from_input(input, Rule::HalfRepeat).unwrap();
+        assert_eq!(part1.breakdown(), solve_breakdown(input, is_invalid_id));
+        assert_eq!(part1.solve(), solve(input));
+
+        let part2 = RangeSetSolver::from_input(input, Rule::AnyRepeat).unwrap();
+        assert_eq!(
+            part2.breakdown(),
+            solve_breakdown(input, is_invalid_id_part2)
+        );
+        assert_eq!(part2.solve(), solve_part2(input));
+
+        assert!(RangeSetSolver::from_input("11-22,oops", Rule::AnyRepeat).is_err());
+    }
+
+    #[test]
+    fn range_set_solver_rejects_queries_outside_the_union() {
+        let solver = RangeSetSolver::new(
+            &[Range { start: 10, end: 30 }, Range { start: 40, end: 60 }],
+            Rule::HalfRepeat,
+        );
+        assert_eq!(solver.sum_for_range(&Range { start: 11, end: 22 }), Ok(33));
+        assert_eq!(solver.count_for_range(&Range { start: 40, end: 60 }), Ok(2));
+
+        for outside in [
+            Range { start: 70, end: 80 },
+            Range { start: 1, end: 5 },
+            Range { start: 25, end: 45 },
+            Range { start: 31, end: 39 },
+            Range { start: 5, end: 11 },
+        ] {
+            let error = UncoveredRangeError { range: outside };
+            assert_eq!(solver.sum_for_range(&outside), Err(error));
+            assert_eq!(solver.count_for_range(&outside), Err(error));
+        }
+        assert_eq!(
+            UncoveredRangeError {
+                range: Range { start: 31, end: 39 }
+            }
+            .to_string(),
+            "range 31-39 is not covered by the prepared ranges"
+        );
+
+        let empty = RangeSetSolver::new(&[], Rule::AnyRepeat);
+        assert!(empty.count_for_range(&Range { start: 1, end: 1 }).is_err());
+        assert_eq!(empty.solve(), 0);
+    }
+
+    #[test]
+    fn range_set_solver_finds_nothing_in_empty_ranges() {
+        let reversed = Range { start: 50, end: 10 };
+        let solver =
+            RangeSetSolver::new(&[Range { start: 10, end: 60 }, reversed], Rule::HalfRepeat);
+        assert_eq!(solver.count_for_range(&reversed), Ok(0));
+        assert_eq!(solver.sum_for_range(&reversed), Ok(0));
+        assert_eq!(
+            solver.breakdown(),
+            vec![(Range { start: 10, end: 60 }, 165), (reversed, 0)]
+        );
+        assert_eq!(solver.solve(), 165);
+
+        let empty = RangeSetSolver::new(&[], Rule::AnyRepeat);
+        assert_eq!(empty.count_for_range(&reversed), Ok(0));
+    }
+
+    #[test]
+    fn extreme_invalid_ids_of_the_example() {
+        let input = EXAMPLE;
+
+        let extremes = extreme_invalid_ids(input, is_invalid_id, 3).unwrap();
+        assert_eq!(extremes.smallest, vec![11, 22, 99]);
+        assert_eq!(extremes.largest, vec![1188511885, 38593859, 446446]);
+
+        let extremes = extreme_invalid_ids(input, is_invalid_id_part2, 4).unwrap();
+        assert_eq!(extremes.smallest, vec![11, 22, 99, 111]);
+        assert_eq!(
+            extremes.largest,
+            vec![2121212121, 1188511885, 824824824, 38593859]
+        );
+    }
+
+    #[test]
+    fn extreme_invalid_ids_returns_fewer_when_k_exceeds_the_matches() {
+        let extremes = extreme_invalid_ids("11-22,95-115", is_invalid_id, 10).unwrap();
+        assert_eq!(extremes.smallest, vec![11, 22, 99]);
+        assert_eq!(extremes.largest, vec![99, 22, 11]);
+
+        let none = extreme_invalid_ids("11-22", is_invalid_id, 0).unwrap();
+        assert_eq!(none, Extremes::default());
+        assert!(none.smallest.is_empty() && none.largest.is_empty());
+    }
+
+    #[test]
+    fn extreme_invalid_ids_counts_overlapping_ids_once() {
+        let extremes = extreme_invalid_ids("10-30,20-40,11-11", is_invalid_id, 2).unwrap();
+        assert_eq!(extremes.smallest, vec![11, 22]);
+        assert_eq!(extremes.largest, vec![33, 22]);
+        assert!(extreme_invalid_ids("11-22,x", is_invalid_id, 2).is_err());
+    }
+
+    #[test]
+    fn streaming_count_and_sum_match_the_collected_ids() {
         let range = Range {
             start: 95,
-            end: 115,
+            end: 1012,
         };
-        let invalid_ids = find_invalid_ids_in_range_part2(&range);
-        assert_eq!(invalid_ids, vec![99, 111]);
+        let ids = find_ids_in_range(&range, is_invalid_id_part2);
+        assert_eq!(
+            count_ids_in_range(&range, is_invalid_id_part2),
+            ids.len() as u64
+        );
+        assert_eq!(
+            sum_ids_in_range(&range, is_invalid_id_part2),
+            ids.iter().sum()
+        );
+        assert_eq!(
+            count_ids_in_range(&Range { start: 12, end: 21 }, is_invalid_id),
+            0
+        );
     }
 
     #[test]
-    fn part2_solves_example() {
-        let input = "11-22,95-115,998-1012,1188511880-1188511890,222220-222224,1698522-1698528,446443-446449,38593856-38593862,565653-565659,824824821-824824827,2121212118-2121212124";
-        assert_eq!(solve_part2(input), 4174379265);
+    fn closed_form_half_repeats_match_brute_force() {
+        let mut seed = 7;
+        for _ in 0..500 {
+            let start = pseudo_random(&mut seed) % 200_000;
+            let end = start + pseudo_random(&mut seed) % 20_000;
+            let range = Range { start, end };
+            assert_eq!(
+                count_half_repeats_in_range(&range),
+                count_ids_in_range(&range, is_invalid_id),
+                "{}",
+                range
+            );
+            assert_eq!(
+                sum_half_repeats_in_range(&range),
+                sum_ids_in_range(&range, is_invalid_id) as u128,
+                "{}",
+                range
+            );
+        }
     }
 
     #[test]
-    fn solves_part2() {
-        let input = include_str!("invalid-ids.txt");
-        assert_eq!(solve_part2(input), 53481866137);
+    fn closed_form_half_repeats_handle_huge_ranges() {
+        let range = Range {
+            start: 1,
+            end: 1_000_000_000_000_000_000,
+        };
+        // 9 + 90 + ... + 900_000_000 halves, one per even length up to 18 digits
+        assert_eq!(count_half_repeats_in_range(&range), 999_999_999);
+        assert!(sum_half_repeats_in_range(&range) > u64::MAX as u128);
+        assert_eq!(
+            sum_half_repeats_in_range(&Range { start: 1, end: 100 }),
+            (11..=99).step_by(11).sum::<u64>() as u128
+        );
     }
 
-    // Refactoring tests
     #[test]
-    fn solve_with_validator_works_with_part1_validator() {
-        let input = "11-22,95-115";
-        let result = solve_with_validator(input, is_invalid_id);
-        assert_eq!(result, 132); // (11 + 22) + 99
+    fn detects_palindromes_of_two_digits_or_more() {
+        for id in [11, 121, 1221, 9999, 1234554321] {
+            assert!(is_invalid_id_palindrome(id), "{}", id);
+        }
+        for id in [0, 5, 10, 1223, 100] {
+            assert!(!is_invalid_id_palindrome(id), "{}", id);
+        }
     }
 
     #[test]
-    fn solve_with_validator_works_with_part2_validator() {
-        let input = "11-22,95-115";
-        let result = solve_with_validator(input, is_invalid_id_part2);
-        assert_eq!(result, 243); // (11 + 22) + (99 + 111)
+    fn palindrome_enumerator_matches_a_scan() {
+        let range = Range {
+            start: 1,
+            end: 100_000,
+        };
+        assert_eq!(
+            find_palindromes_in_range(&range),
+            find_ids_in_range(&range, is_invalid_id_palindrome)
+        );
+
+        let mut seed = 11;
+        for _ in 0..300 {
+            let start = pseudo_random(&mut seed) % 1_000_000;
+            let range = Range {
+                start,
+                end: start + pseudo_random(&mut seed) % 50_000,
+            };
+            assert_eq!(
+                count_palindromes_in_range(&range),
+                count_ids_in_range(&range, is_invalid_id_palindrome),
+                "{}",
+                range
+            );
+        }
+        assert_eq!(solve_palindrome("1-100,95-125"), 495 + 99 + 101 + 111 + 121);
     }
 
     #[test]
-    fn find_ids_in_range_works_with_part1_validator() {
-        let range = Range { start: 95, end: 115 };
-        let invalid_ids = find_ids_in_range(&range, is_invalid_id);
-        assert_eq!(invalid_ids, vec![99]);
+    fn palindrome_enumerator_skips_the_gaps_of_huge_ranges() {
+        let range = Range {
+            start: 1,
+            end: 1_000_000_000_000_000,
+        };
+        // Lengths 2 and 3 have 9 and 90 palindromes, then each pair of lengths
+        // ten times more, up to 9 * 10^7 of length 15
+        assert_eq!(count_palindromes_in_range(&range), 109_999_989);
+        let mut palindromes = iter_palindromes_in_range(&range);
+        assert_eq!(palindromes.next(), Some(11));
+        assert_eq!(palindromes.next_back(), Some(999_999_999_999_999));
+        assert_eq!(palindromes.next_back(), Some(999_999_989_999_999));
+
+        // The next 20-digit palindrome, 18446744077044764481, exceeds u64::MAX
+        let top = Range {
+            start: u64::MAX - 10_000_000_000,
+            end: u64::MAX,
+        };
+        assert_eq!(find_palindromes_in_range(&top), vec![18446744066044764481]);
+        assert_eq!(count_palindromes_in_range(&top), 1);
     }
 
     #[test]
-    fn find_ids_in_range_works_with_part2_validator() {
-        let range = Range { start: 95, end: 115 };
-        let invalid_ids = find_ids_in_range(&range, is_invalid_id_part2);
-        assert_eq!(invalid_ids, vec![99, 111]);
+    fn solve_session_survives_many_interruptions() {
+        let input = EXAMPLE;
+
+        let mut session = SolveSession::new(input).unwrap();
+        let mut suspensions = 0;
+        let answer = loop {
+            match session.run_for(Duration::ZERO) {
+                SessionStatus::Done(sum) => break sum,
+                SessionStatus::InProgress { .. } => {
+                    suspensions += 1;
+                    session = SolveSession::resume(&session.serialize()).unwrap();
+                }
+            }
+        };
+
+        assert!(suspensions >= 10, "{}", suspensions);
+        assert_eq!(answer, 1227775554);
+        assert_eq!(answer, solve(input));
+        assert_eq!(session.run_for(Duration::ZERO), SessionStatus::Done(answer));
+    }
+
+    #[test]
+    fn solve_session_resumes_in_the_middle_of_a_range() {
+        let input = "1-1000,5000-5100";
+        let mut session = SolveSession::new(input).unwrap();
+        assert_eq!(
+            session.run_for(Duration::ZERO),
+            SessionStatus::InProgress {
+                ranges_done: 0,
+                current_offset: 64
+            }
+        );
+
+        let saved = session.serialize();
+        let mut resumed = SolveSession::resume(&saved).unwrap();
+        assert_eq!(resumed, session);
+        assert_eq!(
+            resumed.run_for(Duration::from_secs(60)),
+            SessionStatus::Done(solve(input))
+        );
+    }
+
+    #[test]
+    fn solve_session_rejects_bad_input_and_bytes() {
+        assert!(SolveSession::new("11-22,22-11").is_err());
+        assert_eq!(
+            SolveSession::new("").unwrap().run_for(Duration::ZERO),
+            SessionStatus::Done(0)
+        );
+
+        let saved = SolveSession::new("11-22").unwrap().serialize();
+        assert!(SolveSession::resume(&saved).is_ok());
+        assert!(SolveSession::resume(b"nope").is_err());
+        assert!(SolveSession::resume(&saved[..saved.len() - 1]).is_err());
+        assert!(SolveSession::resume(&saved[..saved.len() - 8]).is_err());
+
+        // Offset 12 is past the end of 11-22
+        let mut past_end = saved.clone();
+        past_end[12..20].copy_from_slice(&12u64.to_le_bytes());
+        assert!(SolveSession::resume(&past_end).is_err());
     }
 }
@@ -1,64 +1,819 @@
+use std::collections::BinaryHeap;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead};
+use std::ops::AddAssign;
+
+/// Batteries turned on per bank in Part 2.
+pub const DEFAULT_BATTERIES: usize = 12;
+
+/// Why a bank cannot produce a joltage.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JoltageError {
+    /// A character that is not a battery digit, at its zero-based column.
+    InvalidBattery { character: char, column: usize },
+    /// The bank has fewer than the `n` batteries to turn on.
+    BankTooShort { n: usize, len: usize },
+    /// The bank has fewer than the two batteries [`max_joltage`] turns on.
+    TooFewDigits { len: usize },
+    /// The joltage of `n` batteries does not fit in a `u64`.
+    Overflow { n: usize },
+    /// The battery count of an annotated line is not a positive number.
+    InvalidCount { count: String },
+    /// A 0 battery, at its zero-based column, in a bank checked in strict mode.
+    ZeroBattery { column: usize },
+    /// Nothing but separators; see [`normalize_bank`].
+    EmptyBank,
+}
+
+impl fmt::Display for JoltageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JoltageError::InvalidBattery { character, column } => {
+                write!(f, "invalid battery {:?} at position {}", character, column)
+            }
+            JoltageError::BankTooShort { n, len } => {
+                write!(f, "bank has fewer than {} batteries (found {})", n, len)
+            }
+            JoltageError::TooFewDigits { len } => {
+                write!(f, "bank has fewer than 2 batteries (found {})", len)
+            }
+            JoltageError::InvalidCount { count } => {
+                write!(f, "invalid battery count {:?}", count)
+            }
+            JoltageError::ZeroBattery { column } => {
+                write!(f, "battery 0 at position {} is not allowed", column)
+            }
+            JoltageError::EmptyBank => write!(f, "bank has no batteries"),
+            JoltageError::Overflow { n } => write!(
+                f,
+                "joltage of {} batteries does not fit in u64; use max_joltage_n_u128",
+                n
+            ),
+        }
+    }
+}
+
+impl Error for JoltageError {}
+
+/// A [`JoltageError`] together with the one-based input line of the bank.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SolveError {
+    pub line: usize,
+    pub source: JoltageError,
+}
+
+impl fmt::Display for SolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.source)
+    }
+}
+
+impl Error for SolveError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
 /// Calculates the maximum joltage from a bank of batteries.
-/// Each bank is a string of digits 1-9. We need to pick exactly two batteries
+/// Each bank is a string of digits. We need to pick exactly two batteries
 /// (digits) from the bank to form a two-digit number, maximizing the result.
+/// The digits keep their place, zeros included: selecting 0 then 5 reads
+/// `05`, which is 5.
+///
+/// # Panics
+/// Panics on a malformed or too short bank; see [`try_max_joltage`].
 pub fn max_joltage(bank: &str) -> u32 {
-    let digits: Vec<u32> = bank.chars().map(|c| c.to_digit(10).unwrap()).collect();
-    let mut max = 0;
-    for i in 0..digits.len() {
-        for j in (i + 1)..digits.len() {
-            let joltage = digits[i] * 10 + digits[j];
-            if joltage > max {
-                max = joltage;
-            }
-        }
+    try_max_joltage(bank).unwrap_or_else(|error| panic!("invalid bank: {error}"))
+}
+
+/// Like [`max_joltage`], but reports non-digit characters and banks with
+/// fewer than two batteries.
+pub fn try_max_joltage(bank: &str) -> Result<u32, JoltageError> {
+    try_max_joltage_selection(bank).map(|(joltage, _)| joltage)
+}
+
+/// Like [`try_max_joltage`]; in `strict` mode banks must hold batteries 1-9
+/// only, as the puzzle describes, and a 0 is reported.
+pub fn try_max_joltage_with(bank: &str, strict: bool) -> Result<u32, JoltageError> {
+    if strict {
+        reject_zeros(bank)?;
     }
-    max
+    try_max_joltage(bank)
+}
+
+/// Like [`max_joltage`], but also returns the positions of the two batteries.
+/// Among equal joltages the earliest positions win: in `9999` the first two
+/// batteries are selected.
+///
+/// # Panics
+/// Panics on a malformed or too short bank.
+pub fn max_joltage_selection(bank: &str) -> (u32, (usize, usize)) {
+    try_max_joltage_selection(bank).unwrap_or_else(|error| panic!("invalid bank: {error}"))
+}
+
+fn try_max_joltage_selection(bank: &str) -> Result<(u32, (usize, usize)), JoltageError> {
+    let digits: Vec<u64> = parse_digits(bank)?;
+    if digits.len() < 2 {
+        return Err(JoltageError::TooFewDigits { len: digits.len() });
+    }
+
+    let indices = greedy_indices(&digits, 2, |a, b| a > b);
+    let (first, second) = (indices[0], indices[1]);
+    Ok((
+        (digits[first] * 10 + digits[second]) as u32,
+        (first, second),
+    ))
 }
 
 /// Calculates the maximum joltage from a bank by picking exactly n batteries.
 /// Uses a greedy approach: at each position, pick the largest digit that
-/// leaves enough remaining digits to complete the selection.
+/// leaves enough remaining digits to complete the selection. A selected
+/// leading 0 keeps its place; the joltage just has fewer significant digits.
+///
+/// # Panics
+/// Panics on a malformed bank or one with fewer than `n` batteries;
+/// see [`try_max_joltage_n`].
 pub fn max_joltage_n(bank: &str, n: usize) -> u64 {
-    let digits: Vec<u64> = bank
-        .chars()
-        .map(|c| c.to_digit(10).unwrap() as u64)
-        .collect();
-    let mut result: u64 = 0;
-    let mut start = 0;
-
-    for remaining in (1..=n).rev() {
-        // We need to pick `remaining` more digits
-        // The latest position we can pick from is len - remaining
-        let end = digits.len() - remaining;
-
-        // Find the maximum digit in range [start, end]
-        let mut max_idx = start;
-        for i in start..=end {
-            if digits[i] > digits[max_idx] {
-                max_idx = i;
+    try_max_joltage_n(bank, n).unwrap_or_else(|error| panic!("invalid bank: {error}"))
+}
+
+/// Like [`max_joltage_n`], but reports non-digit characters and banks with
+/// fewer than `n` batteries.
+pub fn try_max_joltage_n(bank: &str, n: usize) -> Result<u64, JoltageError> {
+    let digits = selectable_digits(bank, n)?;
+    let value = digits_value(&digits, &greedy_indices(&digits, n, |a, b| a > b));
+    u64::try_from(value).map_err(|_| JoltageError::Overflow { n })
+}
+
+/// Like [`try_max_joltage_n`]; in `strict` mode a 0 battery is reported.
+pub fn try_max_joltage_n_with(bank: &str, n: usize, strict: bool) -> Result<u64, JoltageError> {
+    if strict {
+        reject_zeros(bank)?;
+    }
+    try_max_joltage_n(bank, n)
+}
+
+/// Fails on the first 0 battery of the bank.
+fn reject_zeros(bank: &str) -> Result<(), JoltageError> {
+    match bank.find('0') {
+        Some(byte) => Err(JoltageError::ZeroBattery {
+            column: bank[..byte].chars().count(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Like [`max_joltage_n`], for selections too long for `u64` (up to 38 batteries).
+///
+/// # Panics
+/// Panics on a malformed bank, one with fewer than `n` batteries, or when the
+/// result does not fit in `u128`.
+pub fn max_joltage_n_u128(bank: &str, n: usize) -> u128 {
+    let digits = selectable_digits(bank, n).unwrap_or_else(|error| panic!("invalid bank: {error}"));
+    digits_value(&digits, &greedy_indices(&digits, n, |a, b| a > b))
+}
+
+/// Smallest joltage of two batteries, under the same rules as [`min_joltage_n`].
+///
+/// # Panics
+/// Panics on a malformed or too short bank.
+pub fn min_joltage(bank: &str) -> u32 {
+    let digits = parse_digits(bank).unwrap_or_else(|error| panic!("invalid bank: {error}"));
+    if digits.len() < 2 {
+        panic!(
+            "invalid bank: {}",
+            JoltageError::TooFewDigits { len: digits.len() }
+        );
+    }
+    digits_value(&digits, &min_indices(&digits, 2)) as u32
+}
+
+/// Smallest number formed by picking exactly `n` batteries in order: the
+/// greedy selection of [`max_joltage_n`], taking the smallest digit of each
+/// window instead. The leading battery may not be a 0; a leading 0 is only
+/// taken when every battery that could lead is a 0.
+///
+/// # Panics
+/// Panics on a malformed bank or one with fewer than `n` batteries.
+pub fn min_joltage_n(bank: &str, n: usize) -> u64 {
+    let digits = selectable_digits(bank, n).unwrap_or_else(|error| panic!("invalid bank: {error}"));
+    to_u64(digits_value(&digits, &min_indices(&digits, n)))
+}
+
+/// Positions of the smallest selection of `n` digits whose leading digit is
+/// not 0, if avoidable.
+fn min_indices(digits: &[u64], n: usize) -> Vec<usize> {
+    if n == 0 {
+        return Vec::new();
+    }
+    // Rank 0 after every other digit for the lead only
+    let lead_rank = |digit: u64| if digit == 0 { 10 } else { digit };
+    let lead = greedy_indices(&digits[..=digits.len() - n], 1, |a, b| {
+        lead_rank(a) < lead_rank(b)
+    })[0];
+
+    let rest = greedy_indices(&digits[lead + 1..], n - 1, |a, b| a < b);
+    std::iter::once(lead)
+        .chain(rest.into_iter().map(|i| lead + 1 + i))
+        .collect()
+}
+
+/// Like [`max_joltage_n`], but also returns the ascending positions of the
+/// selected batteries.
+///
+/// # Panics
+/// Panics on a malformed bank or one with fewer than `n` batteries.
+pub fn max_joltage_n_indices(bank: &str, n: usize) -> (u64, Vec<usize>) {
+    let digits = selectable_digits(bank, n).unwrap_or_else(|error| panic!("invalid bank: {error}"));
+    let indices = greedy_indices(&digits, n, |a, b| a > b);
+    (to_u64(digits_value(&digits, &indices)), indices)
+}
+
+/// The ascending positions of the batteries [`max_joltage_n`] turns on. Unlike
+/// the joltage itself, these are available however many batteries are selected.
+///
+/// # Panics
+/// Panics on a malformed bank or one with fewer than `n` batteries.
+pub fn max_joltage_indices(bank: &str, n: usize) -> Vec<usize> {
+    let digits = selectable_digits(bank, n).unwrap_or_else(|error| panic!("invalid bank: {error}"));
+    greedy_indices(&digits, n, |a, b| a > b)
+}
+
+/// The digits [`max_joltage_n`] selects, as written, for selections of any
+/// length: leading digits are kept exactly, zeros included.
+///
+/// # Panics
+/// Panics on a malformed bank or one with fewer than `n` batteries.
+pub fn max_joltage_n_str(bank: &str, n: usize) -> String {
+    let digits = selectable_digits(bank, n).unwrap_or_else(|error| panic!("invalid bank: {error}"));
+    greedy_indices(&digits, n, |a, b| a > b)
+        .into_iter()
+        .map(|i| char::from(b'0' + digits[i] as u8))
+        .collect()
+}
+
+/// How spread out the batteries of a [`max_joltage_n_indices`] selection are.
+/// Gaps are the distances between consecutive positions, so adjacent batteries
+/// are 1 apart; a selection of fewer than two batteries has all gaps at 0.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelectionStats {
+    pub value: u64,
+    pub indices: Vec<usize>,
+    pub min_gap: usize,
+    pub max_gap: usize,
+    pub mean_gap: f64,
+}
+
+/// The [`SelectionStats`] of the maximum joltage of `n` batteries, over the
+/// same positions [`max_joltage_indices`] returns.
+///
+/// # Panics
+/// Panics on a malformed bank or one with fewer than `n` batteries.
+pub fn selection_stats(bank: &str, n: usize) -> SelectionStats {
+    let (value, indices) = max_joltage_n_indices(bank, n);
+    let gaps = || indices.windows(2).map(|pair| pair[1] - pair[0]);
+    let mean_gap = match indices.len() {
+        0 | 1 => 0.0,
+        len => (indices[len - 1] - indices[0]) as f64 / (len - 1) as f64,
+    };
+
+    SelectionStats {
+        value,
+        min_gap: gaps().min().unwrap_or(0),
+        max_gap: gaps().max().unwrap_or(0),
+        mean_gap,
+        indices,
+    }
+}
+
+/// Whether every battery of the bank is the same, like `777777777`, so that
+/// any selection gives the same joltage. An empty bank is not constant.
+pub fn is_constant_bank(bank: &str) -> bool {
+    let mut batteries = bank.chars();
+    batteries
+        .next()
+        .is_some_and(|first| batteries.all(|battery| battery == first))
+}
+
+/// Bonus puzzle: the largest joltage of `n` *adjacent* batteries.
+///
+/// # Panics
+/// Panics on a malformed bank or one with fewer than `n` batteries; see
+/// [`try_max_joltage_window`].
+pub fn max_joltage_window(bank: &str, n: usize) -> u64 {
+    try_max_joltage_window(bank, n).unwrap_or_else(|error| panic!("invalid bank: {error}"))
+}
+
+/// Like [`max_joltage_window`], but reports non-digit characters, banks with
+/// fewer than `n` batteries and windows too long for a `u64`.
+///
+/// All windows have the same length, so the best one is found by comparing
+/// digit slices directly; only the winner is turned into a number.
+pub fn try_max_joltage_window(bank: &str, n: usize) -> Result<u64, JoltageError> {
+    let digits = selectable_digits(bank, n)?;
+    let best = (1..=digits.len() - n).fold(0, |best, start| {
+        if digits[start..start + n] > digits[best..best + n] {
+            start
+        } else {
+            best
+        }
+    });
+    digits[best..best + n]
+        .iter()
+        .try_fold(0u64, |value, &digit| {
+            value.checked_mul(10)?.checked_add(digit)
+        })
+        .ok_or(JoltageError::Overflow { n })
+}
+
+/// The `k` largest distinct joltages of two batteries of the bank, largest
+/// first. Fewer are returned when the bank cannot make `k` distinct values.
+///
+/// # Panics
+/// Panics on a malformed bank or one with fewer than two batteries.
+pub fn top_k_joltages(bank: &str, k: usize) -> Vec<u32> {
+    top_k_joltages_n(bank, 2, k)
+        .into_iter()
+        .map(|joltage| joltage as u32)
+        .collect()
+}
+
+/// The `k` largest distinct joltages of `n` batteries of the bank, largest
+/// first.
+///
+/// Searches the tree of picks best first: a node fixes the leading digits, and
+/// its key is the best joltage below it, completed greedily. Each child takes
+/// the leftmost occurrence of its digit, so every value has exactly one leaf
+/// and leaves are reached in descending order without listing all subsets.
+///
+/// # Panics
+/// Panics on a malformed bank, one with fewer than `n` batteries, or a
+/// joltage that does not fit in a `u64`.
+pub fn top_k_joltages_n(bank: &str, n: usize, k: usize) -> Vec<u64> {
+    let digits = selectable_digits(bank, n).unwrap_or_else(|error| panic!("invalid bank: {error}"));
+    let best_below = |prefix: u128, start: usize, remaining: usize| {
+        let rest = &digits[start..];
+        let completion = digits_value(rest, &greedy_indices(rest, remaining, |a, b| a > b));
+        prefix * 10u128.pow(remaining as u32) + completion
+    };
+
+    let mut joltages = Vec::new();
+    // (best joltage below, prefix value, next position, digits still to pick)
+    let mut frontier = BinaryHeap::from([(best_below(0, 0, n), 0, 0, n)]);
+    while joltages.len() < k {
+        let Some((best, prefix, start, remaining)) = frontier.pop() else {
+            break;
+        };
+        if remaining == 0 {
+            joltages.push(to_u64(best));
+            continue;
+        }
+
+        let mut seen = [false; 10];
+        for (i, &digit) in digits
+            .iter()
+            .enumerate()
+            .take(digits.len() - remaining + 1)
+            .skip(start)
+        {
+            if seen[digit as usize] {
+                continue;
             }
+            seen[digit as usize] = true;
+            let prefix = prefix * 10 + u128::from(digit);
+            frontier.push((
+                best_below(prefix, i + 1, remaining - 1),
+                prefix,
+                i + 1,
+                remaining - 1,
+            ));
         }
+    }
+    joltages
+}
+
+/// Parses a bank from which `n` batteries will be selected.
+fn selectable_digits(bank: &str, n: usize) -> Result<Vec<u64>, JoltageError> {
+    let mut digits = Vec::with_capacity(bank.len());
+    selectable_digits_into(bank, n, &mut digits)?;
+    Ok(digits)
+}
 
-        result = result * 10 + digits[max_idx];
-        start = max_idx + 1;
+/// [`selectable_digits`] writing into `digits`, so callers can reuse its allocation.
+fn selectable_digits_into(bank: &str, n: usize, digits: &mut Vec<u64>) -> Result<(), JoltageError> {
+    parse_digits_into(bank, digits)?;
+    if digits.len() < n {
+        return Err(JoltageError::BankTooShort {
+            n,
+            len: digits.len(),
+        });
     }
+    Ok(())
+}
 
-    result
+/// Picks `n` positions greedily: each is the leftmost digit preferred by
+/// `better` that still leaves enough digits after it to complete the selection.
+///
+/// Rather than rescanning a window per pick, this drops the `len - n` digits
+/// that lose to a later one with a monotonic stack, in a single pass.
+fn greedy_indices(digits: &[u64], n: usize, better: impl Fn(u64, u64) -> bool) -> Vec<usize> {
+    let mut stack = Vec::with_capacity(digits.len());
+    greedy_indices_into(digits, n, better, &mut stack);
+    stack
+}
+
+/// [`greedy_indices`] writing into `stack`, so callers can reuse its allocation.
+fn greedy_indices_into(
+    digits: &[u64],
+    n: usize,
+    better: impl Fn(u64, u64) -> bool,
+    stack: &mut Vec<usize>,
+) {
+    let mut drops_left = digits.len() - n;
+    stack.clear();
+
+    for (i, &digit) in digits.iter().enumerate() {
+        // Ties keep the earlier digit, matching the leftmost pick of a rescan
+        while drops_left > 0 && stack.last().is_some_and(|&top| better(digit, digits[top])) {
+            stack.pop();
+            drops_left -= 1;
+        }
+        stack.push(i);
+    }
+
+    stack.truncate(n);
+}
+
+/// Reads the digits at `indices` as a single number.
+fn digits_value(digits: &[u64], indices: &[usize]) -> u128 {
+    indices.iter().fold(0, |value: u128, &i| {
+        value
+            .checked_mul(10)
+            .and_then(|value| value.checked_add(u128::from(digits[i])))
+            .expect("joltage does not fit in u128")
+    })
+}
+
+fn to_u64(value: u128) -> u64 {
+    u64::try_from(value).expect("joltage does not fit in u64")
+}
+
+/// Reads each battery of the bank as a digit, naming the first character that is not one.
+fn parse_digits(bank: &str) -> Result<Vec<u64>, JoltageError> {
+    let mut digits = Vec::with_capacity(bank.len());
+    parse_digits_into(bank, &mut digits)?;
+    Ok(digits)
+}
+
+/// [`parse_digits`] writing into `digits`, so callers can reuse its allocation.
+fn parse_digits_into(bank: &str, digits: &mut Vec<u64>) -> Result<(), JoltageError> {
+    digits.clear();
+    for (column, character) in bank.chars().enumerate() {
+        let digit = character
+            .to_digit(10)
+            .ok_or(JoltageError::InvalidBattery { character, column })?;
+        digits.push(u64::from(digit));
+    }
+    Ok(())
 }
 
 /// Solves the puzzle by summing the maximum joltage from each bank.
+/// Blank lines, including whitespace-only ones, are skipped.
 pub fn solve(input: &str) -> u32 {
-    input.lines().map(max_joltage).sum()
+    solve_lines(input).iter().sum()
+}
+
+/// The maximum joltage of each bank, in input order. Blank lines produce no entry.
+pub fn solve_lines(input: &str) -> Vec<u32> {
+    banks(input).map(max_joltage).collect()
 }
 
 /// Solves Part 2 by summing the maximum joltage (12 batteries each) from each bank.
+/// Blank lines, including whitespace-only ones, are skipped.
 pub fn solve_part2(input: &str) -> u64 {
-    input.lines().map(|line| max_joltage_n(line, 12)).sum()
+    solve_part2_lines(input).iter().sum()
+}
+
+/// The maximum joltage of 12 batteries of each bank, in input order. Blank
+/// lines produce no entry.
+pub fn solve_part2_lines(input: &str) -> Vec<u64> {
+    banks(input)
+        .map(|line| max_joltage_n(line, DEFAULT_BATTERIES))
+        .collect()
+}
+
+/// Like [`solve_part2`], turning on `n` batteries of every bank instead of 12.
+pub fn solve_part2_with_n(input: &str, n: usize) -> u64 {
+    banks(input).map(|line| max_joltage_n(line, n)).sum()
+}
+
+/// Bonus puzzle: sums the [`max_joltage_window`] of `n` adjacent batteries of
+/// each bank. Blank lines are skipped.
+pub fn solve_window(input: &str, n: usize) -> u64 {
+    banks(input).map(|line| max_joltage_window(line, n)).sum()
+}
+
+/// Like [`solve`], leaving out the [constant banks](is_constant_bank).
+pub fn solve_excluding_constant(input: &str) -> u32 {
+    let (_, varied) = partition_banks(input);
+    varied.into_iter().map(max_joltage).sum()
+}
+
+/// Like [`solve_part2`], leaving out the [constant banks](is_constant_bank).
+pub fn solve_part2_excluding_constant(input: &str) -> u64 {
+    let (_, varied) = partition_banks(input);
+    varied
+        .into_iter()
+        .map(|bank| max_joltage_n(bank, DEFAULT_BATTERIES))
+        .sum()
+}
+
+/// Splits the banks of the input into the [constant](is_constant_bank) ones
+/// and the others, each in input order. Blank lines are skipped.
+pub fn partition_banks(input: &str) -> (Vec<&str>, Vec<&str>) {
+    banks(input).partition(|bank| is_constant_bank(bank))
+}
+
+/// Part 2 across the whole input, with the spread of its selections.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SolveStats {
+    /// The Part 2 answer, as [`solve_part2`] computes it.
+    pub total: u64,
+    /// The one-based line and stats of the bank with the largest `max_gap`,
+    /// the first one on ties; `None` for an input without banks.
+    pub widest: Option<(usize, SelectionStats)>,
+}
+
+/// Solves Part 2 collecting the [`selection_stats`] of 12 batteries from each
+/// bank. Blank lines are skipped.
+pub fn solve_part2_stats(input: &str) -> SolveStats {
+    let mut total = 0;
+    let mut widest: Option<(usize, SelectionStats)> = None;
+    for (line, bank) in numbered_banks(input) {
+        let stats = selection_stats(bank, DEFAULT_BATTERIES);
+        total += stats.value;
+        if widest
+            .as_ref()
+            .is_none_or(|(_, best)| stats.max_gap > best.max_gap)
+        {
+            widest = Some((line, stats));
+        }
+    }
+    SolveStats { total, widest }
+}
+
+/// Bonus puzzle: sums the [`min_joltage`] of each bank. Blank lines are skipped.
+pub fn solve_min(input: &str) -> u32 {
+    banks(input).map(min_joltage).sum()
+}
+
+/// Bonus puzzle: sums the [`min_joltage_n`] of 12 batteries from each bank.
+/// Blank lines are skipped.
+pub fn solve_min_part2(input: &str) -> u64 {
+    banks(input)
+        .map(|line| min_joltage_n(line, DEFAULT_BATTERIES))
+        .sum()
+}
+
+/// Sums the maximum joltage of `n` batteries from each bank as a decimal
+/// string, for selections too long for [`solve_part2`]'s `u64` (more than 19
+/// digits). Blank lines are skipped.
+pub fn solve_n_str(input: &str, n: usize) -> String {
+    banks(input)
+        .map(|line| max_joltage_n_str(line, n))
+        .fold("0".to_string(), |sum, joltage| add_decimal(&sum, &joltage))
+}
+
+/// Adds two non-negative decimal numbers written as digit strings.
+fn add_decimal(a: &str, b: &str) -> String {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    let mut digits = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0;
+    for i in 0..a.len().max(b.len()) {
+        let digit_of = |number: &[u8]| {
+            number
+                .len()
+                .checked_sub(i + 1)
+                .map_or(0, |position| number[position] - b'0')
+        };
+        let total = digit_of(a) + digit_of(b) + carry;
+        digits.push(b'0' + total % 10);
+        carry = total / 10;
+    }
+    if carry > 0 {
+        digits.push(b'0' + carry);
+    }
+    while digits.len() > 1 && digits.last() == Some(&b'0') {
+        digits.pop();
+    }
+    digits
+        .iter()
+        .rev()
+        .map(|&digit| char::from(digit))
+        .collect()
+}
+
+/// Tuning for [`solve_parallel_with`] and [`solve_part2_parallel_with`].
+#[cfg(feature = "rayon")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SolveOptions {
+    /// Worker threads to solve on; `None` uses the global rayon pool.
+    pub threads: Option<usize>,
+}
+
+/// Like [`solve`], solving the banks on the rayon pool.
+#[cfg(feature = "rayon")]
+pub fn solve_parallel(input: &str) -> u32 {
+    solve_parallel_with(input, SolveOptions::default())
+}
+
+/// Like [`solve_part2`], solving the banks on the rayon pool.
+#[cfg(feature = "rayon")]
+pub fn solve_part2_parallel(input: &str) -> u64 {
+    solve_part2_parallel_with(input, SolveOptions::default())
+}
+
+/// Like [`solve_parallel`], with the thread count taken from `options`.
+#[cfg(feature = "rayon")]
+pub fn solve_parallel_with(input: &str, options: SolveOptions) -> u32 {
+    u32::try_from(parallel_sum(input, 2, options)).expect("total joltage overflows u32")
+}
+
+/// Like [`solve_part2_parallel`], with the thread count taken from `options`.
+#[cfg(feature = "rayon")]
+pub fn solve_part2_parallel_with(input: &str, options: SolveOptions) -> u64 {
+    parallel_sum(input, DEFAULT_BATTERIES, options)
+}
+
+/// Sums the maximum joltage of `n` batteries of every bank. Each worker keeps
+/// its own [`SelectionBuffers`], so banks are solved without allocating.
+#[cfg(feature = "rayon")]
+fn parallel_sum(input: &str, n: usize, options: SolveOptions) -> u64 {
+    use rayon::prelude::*;
+
+    let banks: Vec<&str> = banks(input).collect();
+    let sum = || {
+        banks
+            .par_iter()
+            .map_init(SelectionBuffers::default, |buffers, bank| {
+                buffers.max_joltage_n(bank, n)
+            })
+            .sum()
+    };
+    match options.threads {
+        Some(threads) => rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .expect("failed to build the rayon thread pool")
+            .install(sum),
+        None => sum(),
+    }
+}
+
+/// Scratch space for solving one bank after another.
+#[cfg(feature = "rayon")]
+#[derive(Default)]
+struct SelectionBuffers {
+    digits: Vec<u64>,
+    stack: Vec<usize>,
+}
+
+#[cfg(feature = "rayon")]
+impl SelectionBuffers {
+    /// Same as [`max_joltage_n`], reusing the buffers.
+    fn max_joltage_n(&mut self, bank: &str, n: usize) -> u64 {
+        selectable_digits_into(bank, n, &mut self.digits)
+            .unwrap_or_else(|error| panic!("invalid bank: {error}"));
+        greedy_indices_into(&self.digits, n, |a, b| a > b, &mut self.stack);
+        let value = digits_value(&self.digits, &self.stack);
+        u64::try_from(value)
+            .unwrap_or_else(|_| panic!("invalid bank: {}", JoltageError::Overflow { n }))
+    }
+}
+
+/// Strips the `-`, `_` and space separators some inputs group batteries with,
+/// as in `987-654 321`. Any other non-digit is reported at its column in `raw`,
+/// and so is a bank left without batteries.
+pub fn normalize_bank(raw: &str) -> Result<String, JoltageError> {
+    let mut bank = String::with_capacity(raw.len());
+    for (column, character) in raw.chars().enumerate() {
+        match character {
+            '0'..='9' => bank.push(character),
+            '-' | '_' | ' ' => {}
+            _ => return Err(JoltageError::InvalidBattery { character, column }),
+        }
+    }
+    if bank.is_empty() {
+        return Err(JoltageError::EmptyBank);
+    }
+    Ok(bank)
+}
+
+/// Like [`solve`], but [normalizes](normalize_bank) each bank and reports the
+/// first bad one with its line number.
+pub fn try_solve(input: &str) -> Result<u32, SolveError> {
+    numbered_banks(input)
+        .map(|(line, raw)| {
+            normalize_bank(raw)
+                .and_then(|bank| try_max_joltage(&bank))
+                .map_err(|source| SolveError { line, source })
+        })
+        .sum()
+}
+
+/// Like [`solve_part2`], but [normalizes](normalize_bank) each bank and
+/// reports the first bad one with its line number.
+pub fn try_solve_part2(input: &str) -> Result<u64, SolveError> {
+    numbered_banks(input)
+        .map(|(line, raw)| {
+            normalize_bank(raw)
+                .and_then(|bank| try_max_joltage_n(&bank, DEFAULT_BATTERIES))
+                .map_err(|source| SolveError { line, source })
+        })
+        .sum()
+}
+
+/// Solves Part 2 with a battery count per bank, written `count|bank`; lines
+/// without a count turn on [`DEFAULT_BATTERIES`]. Reports the first bad line.
+pub fn solve_annotated(input: &str) -> Result<u64, SolveError> {
+    numbered_banks(input)
+        .map(|(line, annotated)| {
+            let (n, bank) = match annotated.split_once('|') {
+                Some((count, bank)) => (parse_count(count), bank),
+                None => (Ok(DEFAULT_BATTERIES), annotated),
+            };
+            n.and_then(|n| try_max_joltage_n(bank, n))
+                .map_err(|source| SolveError { line, source })
+        })
+        .sum()
+}
+
+/// Reads the battery count of an annotated line, which must be at least 1.
+fn parse_count(count: &str) -> Result<usize, JoltageError> {
+    count
+        .trim()
+        .parse()
+        .ok()
+        .filter(|&n| n > 0)
+        .ok_or_else(|| JoltageError::InvalidCount {
+            count: count.to_string(),
+        })
+}
+
+/// Like [`solve`], reading the banks one line at a time from `reader`. A bad
+/// bank is reported as [`io::ErrorKind::InvalidData`] wrapping a [`SolveError`].
+pub fn solve_from_reader<R: BufRead>(reader: R) -> io::Result<u32> {
+    sum_from_reader(reader, try_max_joltage)
+}
+
+/// Like [`solve_part2`], reading the banks one line at a time from `reader`.
+pub fn solve_part2_from_reader<R: BufRead>(reader: R) -> io::Result<u64> {
+    sum_from_reader(reader, |bank| try_max_joltage_n(bank, DEFAULT_BATTERIES))
+}
+
+/// Sums `joltage` over the banks of `reader`, reusing one line buffer. Lines
+/// may end in `\n` or `\r\n`, or not at all; blank lines are skipped.
+fn sum_from_reader<R: BufRead, T: Default + AddAssign>(
+    mut reader: R,
+    joltage: impl Fn(&str) -> Result<T, JoltageError>,
+) -> io::Result<T> {
+    let mut total = T::default();
+    let mut buffer = String::new();
+    let mut line = 0;
+
+    while reader.read_line(&mut buffer)? > 0 {
+        line += 1;
+        let bank = buffer.trim_end_matches(['\n', '\r']);
+        if !bank.trim().is_empty() {
+            total += joltage(bank).map_err(|source| {
+                io::Error::new(io::ErrorKind::InvalidData, SolveError { line, source })
+            })?;
+        }
+        buffer.clear();
+    }
+
+    Ok(total)
+}
+
+/// The non-blank lines of the input, one bank each.
+fn banks(input: &str) -> impl Iterator<Item = &str> {
+    numbered_banks(input).map(|(_, bank)| bank)
+}
+
+/// Like [`banks`], paired with their one-based line numbers.
+fn numbered_banks(input: &str) -> impl Iterator<Item = (usize, &str)> {
+    input
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line))
+        .filter(|(_, line)| !line.trim().is_empty())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::BTreeSet;
 
     #[test]
     fn max_joltage_first_two_batteries_are_largest() {
@@ -122,4 +877,854 @@ mod tests {
         // 987654321111 + 811111111119 + 434234234278 + 888911112111 = 3121910778619
         assert_eq!(solve_part2(input), 3121910778619);
     }
+
+    #[test]
+    fn solve_part2_skips_empty_lines() {
+        let input = "987654321111111\n\n811111111111119\n234234234234278\n818181911112111\n\n";
+        assert_eq!(solve_part2(input), 3121910778619);
+    }
+
+    #[test]
+    fn solvers_skip_trailing_whitespace_lines() {
+        let input = "987654321111111\n811111111111119\n234234234234278\n818181911112111\n  \n\t\n";
+        assert_eq!(solve_part2(input), 3121910778619);
+        assert_eq!(solve(input), 357);
+    }
+
+    #[test]
+    fn try_max_joltage_rejects_single_battery_bank() {
+        assert_eq!(
+            try_max_joltage("7"),
+            Err(JoltageError::TooFewDigits { len: 1 })
+        );
+    }
+
+    #[test]
+    fn try_max_joltage_n_rejects_bank_shorter_than_n() {
+        assert_eq!(
+            try_max_joltage_n("12", 5),
+            Err(JoltageError::BankTooShort { n: 5, len: 2 })
+        );
+    }
+
+    #[test]
+    fn try_variants_agree_with_infallible_ones_on_valid_banks() {
+        assert_eq!(try_max_joltage("818181911112111"), Ok(92));
+        assert_eq!(try_max_joltage_n("818181911112111", 12), Ok(888911112111));
+    }
+
+    #[test]
+    fn try_max_joltage_reports_non_digit_character() {
+        assert_eq!(
+            try_max_joltage("98x7"),
+            Err(JoltageError::InvalidBattery {
+                character: 'x',
+                column: 2
+            })
+        );
+        assert_eq!(
+            try_max_joltage_n("9876\r", 3),
+            Err(JoltageError::InvalidBattery {
+                character: '\r',
+                column: 4
+            })
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid battery 'x' at position 2")]
+    fn max_joltage_panics_with_the_try_error() {
+        max_joltage("98x7");
+    }
+
+    #[test]
+    fn max_joltage_n_indices_reports_selected_positions() {
+        let (value, indices) = max_joltage_n_indices("234234234234278", 12);
+        assert_eq!(value, 434234234278);
+        assert_eq!(value, max_joltage_n("234234234234278", 12));
+        assert_eq!(indices.len(), 12);
+        assert!(indices.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(indices, vec![2, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14]);
+    }
+
+    #[test]
+    fn min_joltage_n_picks_smallest_digits_in_order() {
+        // The 9s at both ends lose to the 1s in between
+        assert_eq!(min_joltage_n("91111119", 2), 11);
+        assert_eq!(min_joltage_n("818181911112111", 3), 111);
+    }
+
+    #[test]
+    fn min_joltage_n_matches_max_for_equal_digits() {
+        assert_eq!(min_joltage_n("5555555", 4), max_joltage_n("5555555", 4));
+    }
+
+    #[test]
+    fn max_joltage_n_u128_handles_selections_beyond_u64() {
+        let bank = "9".repeat(30);
+        assert_eq!(
+            try_max_joltage_n(&bank, 25),
+            Err(JoltageError::Overflow { n: 25 })
+        );
+        assert_eq!(max_joltage_n_u128(&bank, 25), 10u128.pow(25) - 1);
+    }
+
+    #[test]
+    fn max_joltage_n_u128_agrees_with_u64_version() {
+        assert_eq!(
+            max_joltage_n_u128("234234234234278", 12),
+            u128::from(max_joltage_n("234234234234278", 12))
+        );
+    }
+
+    #[test]
+    fn joltage_errors_keep_their_messages() {
+        assert_eq!(
+            JoltageError::InvalidBattery {
+                character: 'x',
+                column: 2
+            }
+            .to_string(),
+            "invalid battery 'x' at position 2"
+        );
+        assert_eq!(
+            JoltageError::BankTooShort { n: 5, len: 2 }.to_string(),
+            "bank has fewer than 5 batteries (found 2)"
+        );
+        assert_eq!(
+            JoltageError::Overflow { n: 25 }.to_string(),
+            "joltage of 25 batteries does not fit in u64; use max_joltage_n_u128"
+        );
+    }
+
+    #[test]
+    fn try_solve_reports_bad_battery_with_its_line() {
+        let input = "987654321111111\n8111111.1111119\n";
+        let error = try_solve(input).unwrap_err();
+        assert_eq!(
+            error,
+            SolveError {
+                line: 2,
+                source: JoltageError::InvalidBattery {
+                    character: '.',
+                    column: 7
+                }
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "line 2: invalid battery '.' at position 7"
+        );
+        assert!(try_solve_part2(input).is_err());
+    }
+
+    #[test]
+    fn try_solve_skips_empty_lines_but_counts_them() {
+        let input = "987654321111111\n\n811111111111119\n234234234234278\n818181911112111\n";
+        assert_eq!(try_solve(input), Ok(357));
+        assert_eq!(try_solve_part2(input), Ok(3121910778619));
+
+        // The short bank sits on the fourth physical line
+        let input = "987654321111111\n\n811111111111119\n12345\n";
+        assert_eq!(
+            try_solve_part2(input),
+            Err(SolveError {
+                line: 4,
+                source: JoltageError::BankTooShort { n: 12, len: 5 }
+            })
+        );
+        assert_eq!(
+            try_solve("9\n"),
+            Err(SolveError {
+                line: 1,
+                source: JoltageError::TooFewDigits { len: 1 }
+            })
+        );
+    }
+
+    fn pseudo_random(seed: &mut u64) -> u64 {
+        *seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        *seed >> 33
+    }
+
+    /// The window-rescanning selection `greedy_indices` replaced, kept as a reference.
+    fn rescanning_indices(
+        digits: &[u64],
+        n: usize,
+        better: impl Fn(u64, u64) -> bool,
+    ) -> Vec<usize> {
+        let mut indices = Vec::with_capacity(n);
+        let mut start = 0;
+
+        for remaining in (1..=n).rev() {
+            // We need to pick `remaining` more digits
+            // The latest position we can pick from is len - remaining
+            let end = digits.len() - remaining;
+
+            // Find the best digit in range [start, end]
+            let mut best_idx = start;
+            for i in start..=end {
+                if better(digits[i], digits[best_idx]) {
+                    best_idx = i;
+                }
+            }
+
+            indices.push(best_idx);
+            start = best_idx + 1;
+        }
+
+        indices
+    }
+
+    #[test]
+    fn stack_selection_matches_rescanning_on_random_banks() {
+        let mut seed = 3;
+        for _ in 0..3000 {
+            let len = 1 + (pseudo_random(&mut seed) % 40) as usize;
+            // Few distinct digits make ties common
+            let spread = 1 + pseudo_random(&mut seed) % 9;
+            let digits: Vec<u64> = (0..len)
+                .map(|_| 1 + pseudo_random(&mut seed) % spread)
+                .collect();
+            let n = 1 + (pseudo_random(&mut seed) as usize) % len;
+
+            let max = |a, b| a > b;
+            let min = |a, b| a < b;
+            assert_eq!(
+                greedy_indices(&digits, n, max),
+                rescanning_indices(&digits, n, max),
+                "{:?} n={}",
+                digits,
+                n
+            );
+            assert_eq!(
+                greedy_indices(&digits, n, min),
+                rescanning_indices(&digits, n, min),
+                "{:?} n={}",
+                digits,
+                n
+            );
+
+            let bank: String = digits.iter().map(|d| d.to_string()).collect();
+            if n <= 19 {
+                assert_eq!(
+                    max_joltage_n(&bank, n) as u128,
+                    digits_value(&digits, &rescanning_indices(&digits, n, max))
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn max_joltage_indices_handles_huge_banks() {
+        let bank = "1".repeat(100_000) + &"9".repeat(100_000);
+        let indices = max_joltage_indices(&bank, 100_000);
+        assert_eq!(indices, (100_000..200_000).collect::<Vec<_>>());
+
+        let mut seed = 5;
+        let bank: String = (0..200_000)
+            .map(|_| char::from(b'1' + (pseudo_random(&mut seed) % 9) as u8))
+            .collect();
+        let indices = max_joltage_indices(&bank, 100_000);
+        assert_eq!(indices.len(), 100_000);
+        assert!(indices.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(
+            max_joltage_indices("234234234234278", 12),
+            max_joltage_n_indices("234234234234278", 12).1
+        );
+    }
+
+    #[test]
+    fn max_joltage_n_str_selects_fifty_of_sixty_digits() {
+        let bank = "314159265358979323846264338327950288419716939937510582097494";
+        assert_eq!(bank.len(), 60);
+        // The 9 and the 8 each drop the five digits before them
+        assert_eq!(
+            max_joltage_n_str(bank, 50),
+            "98979323846264338327950288419716939937510582097494"
+        );
+        assert_eq!(max_joltage_n_str("9081", 3), "981");
+        assert_eq!(max_joltage_n_str("0012", 4), "0012");
+    }
+
+    #[test]
+    fn max_joltage_n_str_agrees_with_the_numeric_api() {
+        for bank in [
+            "987654321111111",
+            "811111111111119",
+            "234234234234278",
+            "818181911112111",
+        ] {
+            for n in 1..=12 {
+                assert_eq!(
+                    max_joltage_n_str(bank, n),
+                    max_joltage_n(bank, n).to_string()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn solve_n_str_sums_beyond_u64() {
+        let input = "314159265358979323846264338327950288419716939937510582097494\n\n".to_string()
+            + &"9".repeat(60);
+        assert_eq!(
+            solve_n_str(&input, 50),
+            "198979323846264338327950288419716939937510582097493"
+        );
+
+        let example = "987654321111111\n811111111111119\n234234234234278\n818181911112111";
+        assert_eq!(solve_n_str(example, 12), solve_part2(example).to_string());
+        assert_eq!(solve_n_str("", 12), "0");
+    }
+
+    #[test]
+    fn max_joltage_selection_of_the_example_banks() {
+        assert_eq!(max_joltage_selection("987654321111111"), (98, (0, 1)));
+        assert_eq!(max_joltage_selection("811111111111119"), (89, (0, 14)));
+        assert_eq!(max_joltage_selection("234234234234278"), (78, (13, 14)));
+        assert_eq!(max_joltage_selection("818181911112111"), (92, (6, 11)));
+        // Ties go to the earliest positions
+        assert_eq!(max_joltage_selection("9999"), (99, (0, 1)));
+    }
+
+    #[test]
+    fn twelve_pick_indices_of_the_example_banks() {
+        assert_eq!(
+            max_joltage_indices("987654321111111", 12),
+            (0..12).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            max_joltage_indices("811111111111119", 12),
+            vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 14]
+        );
+        assert_eq!(
+            max_joltage_indices("234234234234278", 12),
+            vec![2, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14]
+        );
+        assert_eq!(
+            max_joltage_indices("818181911112111", 12),
+            vec![0, 2, 4, 6, 7, 8, 9, 10, 11, 12, 13, 14]
+        );
+    }
+
+    #[test]
+    fn max_joltage_matches_every_pair() {
+        let mut seed = 13;
+        for _ in 0..500 {
+            let bank: String = (0..2 + pseudo_random(&mut seed) % 12)
+                .map(|_| char::from(b'1' + (pseudo_random(&mut seed) % 9) as u8))
+                .collect();
+            let digits: Vec<u32> = bank.chars().map(|c| c.to_digit(10).unwrap()).collect();
+            let best = (0..digits.len())
+                .flat_map(|i| (i + 1..digits.len()).map(move |j| (i, j)))
+                .map(|(i, j)| (digits[i] * 10 + digits[j], (i, j)))
+                // Highest joltage first, then the earliest positions
+                .min_by_key(|&(joltage, indices)| (std::cmp::Reverse(joltage), indices))
+                .unwrap();
+            assert_eq!(max_joltage_selection(&bank), best, "{}", bank);
+        }
+    }
+
+    #[test]
+    fn min_joltage_never_leads_with_a_zero() {
+        // A plain smallest-digit greedy would select 01 and 0012
+        assert_eq!(min_joltage("5019"), 19);
+        assert_eq!(min_joltage_n("30012", 4), 3001);
+        assert_eq!(min_joltage_n("100200", 3), 100);
+        // Zeros after the lead are taken at face value
+        assert_eq!(min_joltage_n("9200", 3), 200);
+        // Only zeros could lead, so the selection is 000
+        assert_eq!(min_joltage_n("0005", 3), 0);
+        // The 5 can still lead, so the zeros before it are skipped
+        assert_eq!(min_joltage_n("0050", 2), 50);
+    }
+
+    #[test]
+    fn min_joltage_of_identical_digits() {
+        assert_eq!(min_joltage("7777"), 77);
+        assert_eq!(min_joltage_n("7777777", 5), 77777);
+        assert_eq!(min_joltage_n("0000", 2), 0);
+    }
+
+    #[test]
+    fn min_joltage_matches_every_pair() {
+        let mut seed = 17;
+        for _ in 0..1000 {
+            let bank: String = (0..10)
+                .map(|_| char::from(b'0' + (pseudo_random(&mut seed) % 10) as u8))
+                .collect();
+            let digits: Vec<u32> = bank.chars().map(|c| c.to_digit(10).unwrap()).collect();
+            let pairs: Vec<(u32, u32)> = (0..10)
+                .flat_map(|i| (i + 1..10).map(move |j| (i, j)))
+                .map(|(i, j)| (digits[i], digits[j]))
+                .collect();
+            let allowed: Vec<(u32, u32)> = if pairs.iter().any(|&(lead, _)| lead != 0) {
+                pairs.into_iter().filter(|&(lead, _)| lead != 0).collect()
+            } else {
+                pairs
+            };
+            let best = allowed.iter().map(|(a, b)| a * 10 + b).min().unwrap();
+            assert_eq!(min_joltage(&bank), best, "{}", bank);
+        }
+    }
+
+    #[test]
+    fn solve_min_sums_the_example() {
+        let input = "987654321111111\n811111111111119\n234234234234278\n818181911112111";
+        assert_eq!(solve_min(input), 11 + 11 + 22 + 11);
+        assert_eq!(
+            solve_min_part2(input),
+            654321111111 + 111111111111 + 223234234278 + 111911112111
+        );
+    }
+
+    #[test]
+    fn zeros_keep_their_place_in_a_selection() {
+        assert_eq!(max_joltage("05"), 5);
+        assert_eq!(max_joltage("0123"), 23);
+        assert_eq!(max_joltage("90"), 90);
+        assert_eq!(max_joltage_n("0102", 4), 102);
+        assert_eq!(max_joltage_n_str("0102", 4), "0102");
+        assert_eq!(max_joltage_n("1020", 3), 120);
+    }
+
+    #[test]
+    fn all_zero_banks_have_zero_joltage() {
+        assert_eq!(max_joltage("0000"), 0);
+        assert_eq!(max_joltage_n("000000", 4), 0);
+        assert_eq!(max_joltage_n_str("000000", 4), "0000");
+    }
+
+    #[test]
+    fn greedy_keeps_room_for_the_remaining_picks() {
+        // The 9 cannot lead: it would leave too few batteries after it
+        assert_eq!(max_joltage_n("1119", 3), 119);
+        assert_eq!(max_joltage_n("0509", 3), 509);
+        assert_eq!(max_joltage_n("2019", 4), 2019);
+    }
+
+    #[test]
+    fn strict_mode_rejects_zero_batteries() {
+        assert_eq!(
+            try_max_joltage_with("9805", true),
+            Err(JoltageError::ZeroBattery { column: 2 })
+        );
+        assert_eq!(try_max_joltage_with("9805", false), Ok(98));
+        assert_eq!(
+            try_max_joltage_n_with("0123", 2, true),
+            Err(JoltageError::ZeroBattery { column: 0 })
+        );
+        assert_eq!(try_max_joltage_n_with("123", 2, true), Ok(23));
+        assert_eq!(
+            JoltageError::ZeroBattery { column: 2 }.to_string(),
+            "battery 0 at position 2 is not allowed"
+        );
+    }
+
+    #[test]
+    fn solve_lines_lists_each_bank() {
+        let input = "987654321111111\n811111111111119\n234234234234278\n818181911112111\n";
+        assert_eq!(solve_lines(input), vec![98, 89, 78, 92]);
+        assert_eq!(solve_lines(input).iter().sum::<u32>(), solve(input));
+
+        let spaced = "\n987654321111111\n\n811111111111119\n234234234234278\n\n818181911112111\n\n";
+        assert_eq!(solve_lines(spaced), solve_lines(input));
+    }
+
+    #[test]
+    fn solve_part2_lines_lists_each_bank() {
+        let input = "987654321111111\n\n811111111111119\n234234234234278\n818181911112111\n\n";
+        let lines = solve_part2_lines(input);
+        assert_eq!(
+            lines,
+            vec![987654321111, 811111111119, 434234234278, 888911112111]
+        );
+        assert_eq!(lines.iter().sum::<u64>(), solve_part2(input));
+    }
+
+    #[test]
+    fn solve_part2_with_n_of_two_matches_part1() {
+        let input = "987654321111111\n811111111111119\n234234234234278\n818181911112111";
+        assert_eq!(solve_part2_with_n(input, 2), u64::from(solve(input)));
+        assert_eq!(solve_part2_with_n(input, 12), solve_part2(input));
+    }
+
+    #[test]
+    fn solve_annotated_mixes_counts_and_defaults() {
+        let input = "2|987654321111111\n811111111111119\n\n3|234234234234278\n12|818181911112111";
+        assert_eq!(
+            solve_annotated(input),
+            Ok(98 + 811111111119 + 478 + 888911112111)
+        );
+    }
+
+    #[test]
+    fn solve_annotated_rejects_bad_counts_with_their_line() {
+        assert_eq!(
+            solve_annotated("987654321111111\n0|811111111111119"),
+            Err(SolveError {
+                line: 2,
+                source: JoltageError::InvalidCount {
+                    count: "0".to_string()
+                }
+            })
+        );
+        assert_eq!(
+            solve_annotated("2|98\n\n16|234234234234278"),
+            Err(SolveError {
+                line: 3,
+                source: JoltageError::BankTooShort { n: 16, len: 15 }
+            })
+        );
+        assert_eq!(
+            solve_annotated("x|98").unwrap_err().to_string(),
+            "line 1: invalid battery count \"x\""
+        );
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel_solvers_match_the_sequential_ones() {
+        let example = "987654321111111\n811111111111119\n234234234234278\n818181911112111";
+        assert_eq!(solve_parallel(example), 357);
+        assert_eq!(solve_part2_parallel(example), 3121910778619);
+
+        let mut seed = 19;
+        let generated: String = (0..10_000)
+            .map(|_| {
+                let bank: String = (0..100)
+                    .map(|_| char::from(b'1' + (pseudo_random(&mut seed) % 9) as u8))
+                    .collect();
+                bank + "\n"
+            })
+            .collect();
+        assert_eq!(solve_parallel(&generated), solve(&generated));
+        assert_eq!(solve_part2_parallel(&generated), solve_part2(&generated));
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel_solvers_honour_the_thread_count() {
+        let input = "987654321111111\n\n811111111111119\n234234234234278\n818181911112111\n";
+        let single = SolveOptions { threads: Some(1) };
+        let first = solve_part2_parallel_with(input, single);
+        assert_eq!(first, solve_part2_parallel_with(input, single));
+        assert_eq!(first, solve_part2(input));
+        assert_eq!(solve_parallel_with(input, single), 357);
+        assert_eq!(
+            solve_part2_parallel_with(input, SolveOptions { threads: Some(4) }),
+            first
+        );
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    #[should_panic(expected = "invalid battery 'x' at position 2")]
+    fn parallel_solver_panics_with_the_try_error() {
+        let single = SolveOptions { threads: Some(1) };
+        solve_part2_parallel_with("987654321111111\n98x7", single);
+    }
+
+    /// Every distinct joltage of `n` batteries of `bank`, largest first.
+    fn brute_force_joltages(bank: &str, n: usize) -> Vec<u64> {
+        fn pick(digits: &[u64], n: usize, value: u64, values: &mut BTreeSet<u64>) {
+            if n == 0 {
+                values.insert(value);
+                return;
+            }
+            for i in 0..digits.len() {
+                pick(&digits[i + 1..], n - 1, value * 10 + digits[i], values);
+            }
+        }
+
+        let digits: Vec<u64> = bank.bytes().map(|b| u64::from(b - b'0')).collect();
+        let mut values = BTreeSet::new();
+        pick(&digits, n, 0, &mut values);
+        values.into_iter().rev().collect()
+    }
+
+    #[test]
+    fn top_k_joltages_lists_the_best_distinct_pairs() {
+        let bank = "818181911112111";
+        assert_eq!(top_k_joltages(bank, 3), vec![92, 91, 89]);
+        let brute_force: Vec<u32> = brute_force_joltages(bank, 2)
+            .into_iter()
+            .take(3)
+            .map(|joltage| joltage as u32)
+            .collect();
+        assert_eq!(top_k_joltages(bank, 3), brute_force);
+        assert_eq!(top_k_joltages(bank, 1), vec![max_joltage(bank)]);
+        assert!(top_k_joltages(bank, 0).is_empty());
+    }
+
+    #[test]
+    fn top_k_joltages_returns_fewer_when_values_run_out() {
+        // 12, 13 and 23 are the only pairs
+        assert_eq!(top_k_joltages("123", 10), vec![23, 13, 12]);
+        assert_eq!(top_k_joltages("1111", 5), vec![11]);
+        assert_eq!(
+            top_k_joltages_n("12121", 3, 100).len(),
+            brute_force_joltages("12121", 3).len()
+        );
+    }
+
+    #[test]
+    fn top_k_joltages_n_matches_brute_force() {
+        let mut seed = 86;
+        for _ in 0..200 {
+            let len = 1 + (pseudo_random(&mut seed) % 12) as usize;
+            let bank: String = (0..len)
+                .map(|_| char::from(b'0' + (pseudo_random(&mut seed) % 4) as u8))
+                .collect();
+            let n = 1 + (pseudo_random(&mut seed) as usize) % len;
+            let k = (pseudo_random(&mut seed) % 20) as usize;
+
+            let expected: Vec<u64> = brute_force_joltages(&bank, n).into_iter().take(k).collect();
+            assert_eq!(
+                top_k_joltages_n(&bank, n, k),
+                expected,
+                "{bank} n={n} k={k}"
+            );
+        }
+        let bank = "987654321111111";
+        assert_eq!(
+            top_k_joltages_n(bank, DEFAULT_BATTERIES, 1),
+            vec![max_joltage_n(bank, DEFAULT_BATTERIES)]
+        );
+    }
+
+    #[test]
+    fn reader_solvers_match_the_string_solvers() {
+        let example = "987654321111111\n811111111111119\n234234234234278\n818181911112111\n";
+        assert_eq!(solve_from_reader(io::Cursor::new(example)).unwrap(), 357);
+        assert_eq!(
+            solve_part2_from_reader(io::Cursor::new(example)).unwrap(),
+            3121910778619
+        );
+
+        // No newline after the last bank
+        let unterminated = example.trim_end();
+        assert_eq!(solve_from_reader(unterminated.as_bytes()).unwrap(), 357);
+        assert_eq!(
+            solve_part2_from_reader(unterminated.as_bytes()).unwrap(),
+            3121910778619
+        );
+    }
+
+    #[test]
+    fn reader_solvers_accept_crlf_line_endings() {
+        let crlf =
+            "987654321111111\r\n811111111111119\r\n\r\n234234234234278\r\n818181911112111\r\n";
+        assert_eq!(solve_from_reader(io::Cursor::new(crlf)).unwrap(), 357);
+        assert_eq!(
+            solve_part2_from_reader(io::Cursor::new(crlf)).unwrap(),
+            3121910778619
+        );
+    }
+
+    #[test]
+    fn reader_solvers_report_bad_banks_as_invalid_data() {
+        let error = solve_from_reader(io::Cursor::new("12\n1x3\n")).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            error.to_string(),
+            "line 2: invalid battery 'x' at position 1"
+        );
+    }
+
+    #[test]
+    fn max_joltage_window_reads_adjacent_batteries() {
+        assert_eq!(max_joltage_window("987654321111111", 3), 987);
+        assert_eq!(max_joltage_window("811111111111119", 3), 811);
+        assert_eq!(max_joltage_window("234234234234278", 3), 427);
+        assert_eq!(max_joltage_window("818181911112111", 3), 911);
+        let input = "987654321111111\n811111111111119\n234234234234278\n818181911112111";
+        assert_eq!(solve_window(input, 3), 987 + 811 + 427 + 911);
+    }
+
+    #[test]
+    fn max_joltage_window_handles_the_extreme_lengths() {
+        assert_eq!(max_joltage_window("3141592", 1), 9);
+        assert_eq!(max_joltage_window("3141592", 7), 3141592);
+        assert_eq!(
+            try_max_joltage_window("314", 4),
+            Err(JoltageError::BankTooShort { n: 4, len: 3 })
+        );
+        assert_eq!(
+            try_max_joltage_window(&"9".repeat(20), 20),
+            Err(JoltageError::Overflow { n: 20 })
+        );
+    }
+
+    #[test]
+    fn max_joltage_window_matches_a_brute_force_scan() {
+        let mut seed = 90;
+        for _ in 0..500 {
+            let len = 1 + (pseudo_random(&mut seed) % 30) as usize;
+            let bank: String = (0..len)
+                .map(|_| char::from(b'0' + (pseudo_random(&mut seed) % 10) as u8))
+                .collect();
+            let n = 1 + (pseudo_random(&mut seed) as usize) % len.min(18);
+
+            let expected = (0..=len - n)
+                .map(|start| bank[start..start + n].parse::<u64>().unwrap())
+                .max()
+                .unwrap();
+            assert_eq!(max_joltage_window(&bank, n), expected, "{bank} n={n}");
+        }
+    }
+
+    #[test]
+    fn selection_stats_of_the_readme_banks() {
+        let stats = selection_stats("987654321111111", 12);
+        assert_eq!(stats.value, 987654321111);
+        assert_eq!(stats.indices, (0..12).collect::<Vec<_>>());
+        assert_eq!((stats.min_gap, stats.max_gap, stats.mean_gap), (1, 1, 1.0));
+
+        // The 9 at the end is picked after eleven leading batteries
+        let stats = selection_stats("811111111111119", 12);
+        assert_eq!(stats.value, 811111111119);
+        assert_eq!((stats.min_gap, stats.max_gap), (1, 4));
+        assert_eq!(stats.mean_gap, 14.0 / 11.0);
+
+        let stats = selection_stats("234234234234278", 12);
+        assert_eq!(stats.value, 434234234278);
+        assert_eq!(stats.indices[..3], [2, 4, 5]);
+        assert_eq!((stats.min_gap, stats.max_gap), (1, 2));
+        assert_eq!(stats.mean_gap, 12.0 / 11.0);
+
+        let stats = selection_stats("818181911112111", 12);
+        assert_eq!(stats.value, 888911112111);
+        assert_eq!(stats.indices[..4], [0, 2, 4, 6]);
+        assert_eq!((stats.min_gap, stats.max_gap), (1, 2));
+        assert_eq!(stats.mean_gap, 14.0 / 11.0);
+    }
+
+    #[test]
+    fn selection_stats_uses_the_canonical_indices() {
+        // Many selections tie on the value; the stats follow the leftmost one
+        for bank in ["11111", "9090909", "5555155551"] {
+            for n in 1..=bank.len() {
+                assert_eq!(
+                    selection_stats(bank, n).indices,
+                    max_joltage_indices(bank, n)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn selection_stats_of_a_whole_bank_has_unit_gaps() {
+        let stats = selection_stats("31415926", 8);
+        assert_eq!(stats.value, 31415926);
+        assert_eq!((stats.min_gap, stats.max_gap, stats.mean_gap), (1, 1, 1.0));
+
+        let single = selection_stats("31415926", 1);
+        assert_eq!(single.indices, vec![5]);
+        assert_eq!(
+            (single.min_gap, single.max_gap, single.mean_gap),
+            (0, 0, 0.0)
+        );
+    }
+
+    #[test]
+    fn solve_part2_stats_finds_the_widest_selection() {
+        let input = "987654321111111\n811111111111119\n\n234234234234278\n818181911112111\n";
+        let stats = solve_part2_stats(input);
+        assert_eq!(stats.total, solve_part2(input));
+        let (line, widest) = stats.widest.unwrap();
+        assert_eq!(line, 2);
+        assert_eq!(widest.max_gap, 4);
+        assert_eq!(solve_part2_stats("\n").widest, None);
+    }
+
+    #[test]
+    fn is_constant_bank_needs_a_single_battery_value() {
+        assert!(is_constant_bank("777777777"));
+        assert!(is_constant_bank("3"));
+        assert!(!is_constant_bank("777777787"));
+        assert!(!is_constant_bank(""));
+    }
+
+    #[test]
+    fn excluding_constant_banks_changes_nothing_without_them() {
+        let example = "987654321111111\n811111111111119\n234234234234278\n818181911112111";
+        assert_eq!(solve_excluding_constant(example), solve(example));
+        assert_eq!(
+            solve_part2_excluding_constant(example),
+            solve_part2(example)
+        );
+        assert!(partition_banks(example).0.is_empty());
+    }
+
+    #[test]
+    fn constant_banks_are_partitioned_and_excluded() {
+        let input = "987654321111111\n777777777777777\n811111111111119\n\n\
+                     234234234234278\n111111111111\n818181911112111\n";
+
+        let (constant, varied) = partition_banks(input);
+
+        assert_eq!(constant, vec!["777777777777777", "111111111111"]);
+        assert_eq!(
+            varied,
+            vec![
+                "987654321111111",
+                "811111111111119",
+                "234234234234278",
+                "818181911112111"
+            ]
+        );
+        assert_eq!(solve(input), 357 + 77 + 11);
+        assert_eq!(solve_excluding_constant(input), 357);
+        assert_eq!(
+            solve_part2(input),
+            3121910778619 + 777777777777 + 111111111111
+        );
+        assert_eq!(solve_part2_excluding_constant(input), 3121910778619);
+    }
+
+    #[test]
+    fn normalize_bank_strips_separators() {
+        assert_eq!(normalize_bank("987-654-321"), Ok("987654321".to_string()));
+        assert_eq!(normalize_bank("987 654 321"), Ok("987654321".to_string()));
+        assert_eq!(normalize_bank("98-7_65 4 321"), Ok("987654321".to_string()));
+        assert_eq!(
+            normalize_bank("818181911112111"),
+            Ok("818181911112111".to_string())
+        );
+    }
+
+    #[test]
+    fn normalize_bank_rejects_other_characters_and_empty_banks() {
+        assert_eq!(
+            normalize_bank("987-65x"),
+            Err(JoltageError::InvalidBattery {
+                character: 'x',
+                column: 6
+            })
+        );
+        assert_eq!(normalize_bank("- _ -"), Err(JoltageError::EmptyBank));
+        assert_eq!(
+            try_solve("12\n--\n"),
+            Err(SolveError {
+                line: 2,
+                source: JoltageError::EmptyBank
+            })
+        );
+    }
+
+    #[test]
+    fn try_solve_accepts_grouped_banks() {
+        let example = "987654321111111\n811111111111119\n234234234234278\n818181911112111";
+        assert_eq!(try_solve(example), Ok(solve(example)));
+        assert_eq!(try_solve_part2(example), Ok(solve_part2(example)));
+
+        let grouped =
+            "987-654-321-111-111\n811 111 111 111 119\n234_234_234 234-278\n818181911112111\n";
+        assert_eq!(try_solve(grouped), Ok(357));
+        assert_eq!(try_solve_part2(grouped), Ok(3121910778619));
+    }
 }
@@ -1,9 +1,21 @@
-use day3::{solve, solve_part2};
+use day3::{solve_from_reader, solve_part2_from_reader};
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
 
-fn main() {
-    let input = include_str!("batteries.txt");
-    let result = solve(input);
+/// Solves the embedded puzzle input, or the file given as the first argument.
+fn main() -> io::Result<()> {
+    let path = env::args().nth(1);
+    let open = || -> io::Result<Box<dyn BufRead>> {
+        Ok(match &path {
+            Some(path) => Box::new(BufReader::new(File::open(path)?)),
+            None => Box::new(include_str!("batteries.txt").as_bytes()),
+        })
+    };
+
+    let result = solve_from_reader(open()?)?;
     println!("Part 1: {}", result);
-    let result_part2 = solve_part2(input);
+    let result_part2 = solve_part2_from_reader(open()?)?;
     println!("Part 2: {}", result_part2);
+    Ok(())
 }
@@ -1,51 +1,233 @@
-pub fn count_accessible_rolls(grid: &str) -> usize {
-    let lines: Vec<&str> = grid.lines().collect();
-    let rows = lines.len();
-    if rows == 0 {
-        return 0;
-    }
-    let cols = lines[0].len();
-
-    let mut count = 0;
-    for (row, line) in lines.iter().enumerate() {
-        for (col, ch) in line.chars().enumerate() {
-            if ch == '@' && count_neighbors(&lines, row, col, rows, cols) < 4 {
-                count += 1;
-            }
+use std::error::Error;
+use std::fmt;
+
+/// Neighbor count below which a roll can be reached by a forklift.
+const DEFAULT_THRESHOLD: usize = 4;
+
+/// A cell that is neither a roll `@`, empty `.`, nor whitespace, at its
+/// zero-based row and column.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GridParseError {
+    pub character: char,
+    pub row: usize,
+    pub col: usize,
+}
+
+impl fmt::Display for GridParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid cell {:?} at row {}, column {}",
+            self.character, self.row, self.col
+        )
+    }
+}
+
+impl Error for GridParseError {}
+
+/// Which surrounding cells count as neighbors of a roll.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Adjacency {
+    /// Only the cells above, below, left and right.
+    Orthogonal,
+    /// All eight surrounding cells, diagonals included.
+    #[default]
+    All,
+}
+
+impl Adjacency {
+    fn offsets(self) -> &'static [(i32, i32)] {
+        match self {
+            Adjacency::Orthogonal => &[(-1, 0), (0, -1), (0, 1), (1, 0)],
+            Adjacency::All => &[
+                (-1, -1),
+                (-1, 0),
+                (-1, 1),
+                (0, -1),
+                (0, 1),
+                (1, -1),
+                (1, 0),
+                (1, 1),
+            ],
         }
     }
-    count
 }
 
+/// # Panics
+/// Panics on a cell that is not a roll, empty or whitespace; see
+/// [`try_count_accessible_rolls`].
+pub fn count_accessible_rolls(grid: &str) -> usize {
+    count_accessible_rolls_with_threshold(grid, DEFAULT_THRESHOLD)
+}
+
+/// Like [`count_accessible_rolls`], but reports the first invalid cell.
+pub fn try_count_accessible_rolls(grid: &str) -> Result<usize, GridParseError> {
+    let grid = try_parse_grid(grid)?;
+    Ok(find_accessible_positions(&grid, DEFAULT_THRESHOLD, Adjacency::default()).len())
+}
+
+/// Like [`count_accessible_rolls`], but a roll is accessible when it has fewer
+/// than `threshold` neighboring rolls.
+pub fn count_accessible_rolls_with_threshold(grid: &str, threshold: usize) -> usize {
+    count_accessible(grid, threshold, Adjacency::default())
+}
+
+/// Like [`count_accessible_rolls`], counting neighbors according to `adjacency`.
+pub fn count_accessible_rolls_with_adjacency(grid: &str, adjacency: Adjacency) -> usize {
+    count_accessible(grid, DEFAULT_THRESHOLD, adjacency)
+}
+
+/// Positions `(row, col)` of the rolls [`count_accessible_rolls`] counts, in
+/// row-major order.
+pub fn accessible_positions(grid: &str) -> Vec<(usize, usize)> {
+    find_accessible_positions(&parse_grid(grid), DEFAULT_THRESHOLD, Adjacency::default())
+}
+
+fn count_accessible(grid: &str, threshold: usize, adjacency: Adjacency) -> usize {
+    find_accessible_positions(&parse_grid(grid), threshold, adjacency).len()
+}
+
+/// # Panics
+/// Panics on a cell that is not a roll, empty or whitespace; see
+/// [`try_count_total_removable_rolls`].
 pub fn count_total_removable_rolls(grid: &str) -> usize {
-    let mut grid: Vec<Vec<char>> = grid.lines().map(|line| line.chars().collect()).collect();
-    let rows = grid.len();
-    if rows == 0 {
-        return 0;
-    }
-    let cols = grid[0].len();
+    remove_all_accessible(grid).0
+}
 
-    let mut total_removed = 0;
+/// Like [`count_total_removable_rolls`], but reports the first invalid cell.
+pub fn try_count_total_removable_rolls(grid: &str) -> Result<usize, GridParseError> {
+    let mut grid = try_parse_grid(grid)?;
+    Ok(
+        remove_rolls(&mut grid, DEFAULT_THRESHOLD, Adjacency::default())
+            .iter()
+            .sum(),
+    )
+}
 
-    loop {
-        let accessible = find_accessible_positions(&grid, rows, cols);
-        if accessible.is_empty() {
-            break;
-        }
-        for (row, col) in &accessible {
-            grid[*row][*col] = '.';
+/// Removes accessible rolls until none are left, returning how many were
+/// removed and the grid that remains.
+pub fn remove_all_accessible(grid: &str) -> (usize, Vec<Vec<char>>) {
+    let mut grid = parse_grid(grid);
+    let waves = remove_rolls(&mut grid, DEFAULT_THRESHOLD, Adjacency::default());
+    (waves.iter().sum(), grid)
+}
+
+/// Like [`count_total_removable_rolls`], with the accessibility threshold of
+/// [`count_accessible_rolls_with_threshold`].
+pub fn count_total_removable_rolls_with_threshold(grid: &str, threshold: usize) -> usize {
+    count_removable(grid, threshold, Adjacency::default())
+}
+
+/// Like [`count_total_removable_rolls`], counting neighbors according to `adjacency`.
+pub fn count_total_removable_rolls_with_adjacency(grid: &str, adjacency: Adjacency) -> usize {
+    count_removable(grid, DEFAULT_THRESHOLD, adjacency)
+}
+
+/// How many rolls [`count_total_removable_rolls`] removes in each round: the
+/// rolls accessible at first, then those accessible once the previous round is
+/// gone, and so on. A grid with nothing to remove has no rounds.
+pub fn removal_rounds(grid: &str) -> Vec<usize> {
+    remove_rolls(
+        &mut parse_grid(grid),
+        DEFAULT_THRESHOLD,
+        Adjacency::default(),
+    )
+}
+
+fn count_removable(grid: &str, threshold: usize, adjacency: Adjacency) -> usize {
+    remove_rolls(&mut parse_grid(grid), threshold, adjacency)
+        .iter()
+        .sum()
+}
+
+/// Removes accessible rolls from `grid` in rounds, returning how many were
+/// removed in each.
+///
+/// Rather than rescanning the grid every round, this keeps the neighbor count
+/// of each roll and peels a worklist: removing a roll decrements its
+/// neighbors, and those that drop below `threshold` join the next round.
+fn remove_rolls(grid: &mut [Vec<char>], threshold: usize, adjacency: Adjacency) -> Vec<usize> {
+    let mut neighbors: Vec<Vec<usize>> = (0..grid.len())
+        .map(|row| {
+            (0..grid[row].len())
+                .map(|col| count_neighbors_grid(grid, row, col, adjacency))
+                .collect()
+        })
+        .collect();
+    let mut queued: Vec<Vec<bool>> = grid.iter().map(|line| vec![false; line.len()]).collect();
+
+    let mut round = find_accessible_positions(grid, threshold, adjacency);
+    for &(row, col) in &round {
+        queued[row][col] = true;
+    }
+
+    let mut rounds = Vec::new();
+    while !round.is_empty() {
+        let mut next_round = Vec::new();
+        for &(row, col) in &round {
+            grid[row][col] = '.';
+            for (nr, nc) in neighbor_positions(grid, row, col, adjacency) {
+                // Rolls already queued are removed whatever their count
+                if grid[nr][nc] != '@' || queued[nr][nc] {
+                    continue;
+                }
+                neighbors[nr][nc] -= 1;
+                if neighbors[nr][nc] < threshold {
+                    queued[nr][nc] = true;
+                    next_round.push((nr, nc));
+                }
+            }
         }
-        total_removed += accessible.len();
+        rounds.push(round.len());
+        round = next_round;
     }
 
-    total_removed
+    rounds
+}
+
+fn parse_grid(grid: &str) -> Vec<Vec<char>> {
+    try_parse_grid(grid).unwrap_or_else(|error| panic!("invalid grid: {error}"))
 }
 
-fn find_accessible_positions(grid: &[Vec<char>], rows: usize, cols: usize) -> Vec<(usize, usize)> {
+/// Reads the grid as a rectangle: whitespace, such as a `\r` left by CRLF line
+/// endings, is an empty cell, and rows shorter than the widest one are padded
+/// with empty cells.
+fn try_parse_grid(grid: &str) -> Result<Vec<Vec<char>>, GridParseError> {
+    let mut rows = grid
+        .lines()
+        .enumerate()
+        .map(|(row, line)| {
+            line.chars()
+                .enumerate()
+                .map(|(col, character)| match character {
+                    '@' | '.' => Ok(character),
+                    _ if character.is_whitespace() => Ok('.'),
+                    _ => Err(GridParseError {
+                        character,
+                        row,
+                        col,
+                    }),
+                })
+                .collect::<Result<Vec<char>, _>>()
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let width = rows.iter().map(Vec::len).max().unwrap_or(0);
+    for row in &mut rows {
+        row.resize(width, '.');
+    }
+    Ok(rows)
+}
+
+fn find_accessible_positions(
+    grid: &[Vec<char>],
+    threshold: usize,
+    adjacency: Adjacency,
+) -> Vec<(usize, usize)> {
     let mut accessible = Vec::new();
-    for row in 0..rows {
-        for col in 0..cols {
-            if grid[row][col] == '@' && count_neighbors_grid(grid, row, col, rows, cols) < 4 {
+    for (row, line) in grid.iter().enumerate() {
+        for (col, &cell) in line.iter().enumerate() {
+            if cell == '@' && count_neighbors_grid(grid, row, col, adjacency) < threshold {
                 accessible.push((row, col));
             }
         }
@@ -53,54 +235,26 @@ fn find_accessible_positions(grid: &[Vec<char>], rows: usize, cols: usize) -> Ve
     accessible
 }
 
-fn count_neighbors_grid(
+fn count_neighbors_grid(grid: &[Vec<char>], row: usize, col: usize, adjacency: Adjacency) -> usize {
+    neighbor_positions(grid, row, col, adjacency)
+        .filter(|&(nr, nc)| grid[nr][nc] == '@')
+        .count()
+}
+
+/// The cells around `(row, col)` that exist in `grid`.
+fn neighbor_positions(
     grid: &[Vec<char>],
     row: usize,
     col: usize,
-    rows: usize,
-    cols: usize,
-) -> usize {
-    let mut neighbors = 0;
-    for dr in -1i32..=1 {
-        for dc in -1i32..=1 {
-            if dr == 0 && dc == 0 {
-                continue;
-            }
-            let nr = row as i32 + dr;
-            let nc = col as i32 + dc;
-            if nr >= 0
-                && nr < rows as i32
-                && nc >= 0
-                && nc < cols as i32
-                && grid[nr as usize][nc as usize] == '@'
-            {
-                neighbors += 1;
-            }
-        }
-    }
-    neighbors
-}
-
-fn count_neighbors(lines: &[&str], row: usize, col: usize, rows: usize, cols: usize) -> usize {
-    let mut neighbors = 0;
-    for dr in -1i32..=1 {
-        for dc in -1i32..=1 {
-            if dr == 0 && dc == 0 {
-                continue;
-            }
-            let nr = row as i32 + dr;
-            let nc = col as i32 + dc;
-            if nr >= 0
-                && nr < rows as i32
-                && nc >= 0
-                && nc < cols as i32
-                && lines[nr as usize].chars().nth(nc as usize) == Some('@')
-            {
-                neighbors += 1;
-            }
-        }
-    }
-    neighbors
+    adjacency: Adjacency,
+) -> impl Iterator<Item = (usize, usize)> + use<'_> {
+    adjacency.offsets().iter().filter_map(move |&(dr, dc)| {
+        let nr = row.checked_add_signed(dr as isize)?;
+        let nc = col.checked_add_signed(dc as isize)?;
+        // Cells outside the grid count as empty
+        grid.get(nr)?.get(nc)?;
+        Some((nr, nc))
+    })
 }
 
 #[cfg(test)]
@@ -173,4 +327,287 @@ mod tests {
         println!("Puzzle answer part 2: {}", result);
         assert!(result > 0);
     }
+
+    #[test]
+    fn large_full_grid_is_counted_quickly() {
+        // In a solid block only the corners have fewer than 4 neighbors
+        let row = "@".repeat(1000);
+        let grid = vec![row.as_str(); 1000].join("\n");
+        assert_eq!(count_accessible_rolls(&grid), 4);
+    }
+
+    #[test]
+    fn higher_threshold_makes_center_of_plus_accessible() {
+        let grid = ".@.\n@@@\n.@.";
+        assert_eq!(count_accessible_rolls_with_threshold(grid, 5), 5);
+        assert_eq!(count_accessible_rolls_with_threshold(grid, 4), 4);
+    }
+
+    #[test]
+    fn lower_threshold_can_block_all_removals() {
+        // Each outer roll touches the other three rolls of the plus
+        let grid = ".@.\n@@@\n.@.";
+        assert_eq!(count_total_removable_rolls_with_threshold(grid, 4), 5);
+        assert_eq!(count_total_removable_rolls_with_threshold(grid, 3), 0);
+    }
+
+    #[test]
+    fn orthogonal_adjacency_ignores_diagonal_neighbors() {
+        // The center roll only has rolls on its four diagonals
+        let grid = "@.@\n.@.\n@.@";
+        assert_eq!(
+            count_accessible_rolls_with_adjacency(grid, Adjacency::All),
+            4
+        );
+        assert_eq!(
+            count_accessible_rolls_with_adjacency(grid, Adjacency::Orthogonal),
+            5
+        );
+        assert_eq!(count_accessible_rolls(grid), 4);
+    }
+
+    #[test]
+    fn orthogonal_adjacency_removes_whole_solid_block() {
+        let grid = "@@@\n@@@\n@@@";
+        assert_eq!(
+            count_total_removable_rolls_with_adjacency(grid, Adjacency::Orthogonal),
+            9
+        );
+        assert_eq!(
+            count_total_removable_rolls_with_adjacency(grid, Adjacency::All),
+            count_total_removable_rolls(grid)
+        );
+    }
+
+    #[test]
+    fn removal_rounds_on_example() {
+        let grid = "\
+..@@.@@@@.
+@@@.@.@.@@
+@@@@@.@.@@
+@.@@@@..@.
+@@.@@@@.@@
+.@@@@@@@.@
+.@.@.@.@@@
+@.@@@.@@@@
+.@@@@@@@@.
+@.@.@@@.@.";
+        let rounds = removal_rounds(grid);
+        assert_eq!(
+            rounds.iter().sum::<usize>(),
+            count_total_removable_rolls(grid)
+        );
+        assert_eq!(rounds.iter().sum::<usize>(), 43);
+        assert_eq!(rounds.len(), 9);
+        assert_eq!(rounds[0], count_accessible_rolls(grid));
+        assert_eq!(rounds, rescanning_rounds(grid, 4, Adjacency::All));
+    }
+
+    #[test]
+    fn removal_rounds_of_grid_without_rolls() {
+        assert_eq!(removal_rounds("...\n..."), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn accessible_positions_lists_outer_rolls_of_plus() {
+        let grid = ".@.\n@@@\n.@.";
+        assert_eq!(
+            accessible_positions(grid),
+            vec![(0, 1), (1, 0), (1, 2), (2, 1)]
+        );
+    }
+
+    #[test]
+    fn ragged_rows_use_their_own_length() {
+        // Row 0 is one cell wide: a width taken from it would drop the rolls
+        // at the end of the middle row, and the short last row would be
+        // indexed past its end
+        let grid = "@\n@@..@@\n.@";
+        assert_eq!(count_accessible_rolls(grid), 6);
+        assert_eq!(
+            accessible_positions(grid),
+            vec![(0, 0), (1, 0), (1, 1), (1, 4), (1, 5), (2, 1)]
+        );
+    }
+
+    #[test]
+    fn ragged_rows_count_missing_cells_as_empty() {
+        // (1, 2) only touches (0, 1), (0, 2) and (1, 1): row 2 ends before it
+        let grid = "@@@\n@@@\n@";
+        assert_eq!(
+            accessible_positions(grid),
+            vec![(0, 0), (0, 2), (1, 2), (2, 0)]
+        );
+    }
+
+    #[test]
+    fn remove_all_accessible_returns_remaining_grid() {
+        let grid = "\
+..@@.@@@@.
+@@@.@.@.@@
+@@@@@.@.@@
+@.@@@@..@.
+@@.@@@@.@@
+.@@@@@@@.@
+.@.@.@.@@@
+@.@@@.@@@@
+.@@@@@@@@.
+@.@.@@@.@.";
+        let rolls = grid.chars().filter(|&c| c == '@').count();
+        let (removed, remaining) = remove_all_accessible(grid);
+        assert_eq!(removed, 43);
+
+        let leftover_rolls = rolls - removed;
+        let cells: Vec<char> = remaining.into_iter().flatten().collect();
+        let dots = cells.iter().filter(|&&c| c == '.').count();
+        assert_eq!(cells.len(), 100);
+        assert_eq!(dots, 100 - leftover_rolls);
+    }
+
+    fn pseudo_random(seed: &mut u64) -> u64 {
+        *seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        *seed >> 33
+    }
+
+    /// The removal as first written: rescan the whole grid every round.
+    fn rescanning_rounds(grid: &str, threshold: usize, adjacency: Adjacency) -> Vec<usize> {
+        let mut grid = parse_grid(grid);
+        let mut rounds = Vec::new();
+
+        loop {
+            let accessible = find_accessible_positions(&grid, threshold, adjacency);
+            if accessible.is_empty() {
+                break;
+            }
+            for (row, col) in &accessible {
+                grid[*row][*col] = '.';
+            }
+            rounds.push(accessible.len());
+        }
+
+        rounds
+    }
+
+    #[test]
+    fn worklist_removal_matches_rescanning() {
+        let mut seed = 98;
+        for _ in 0..200 {
+            let rows = 1 + (pseudo_random(&mut seed) % 15) as usize;
+            let density = 1 + pseudo_random(&mut seed) % 9;
+            let grid: Vec<String> = (0..rows)
+                .map(|_| {
+                    let width = 1 + (pseudo_random(&mut seed) % 15) as usize;
+                    (0..width)
+                        .map(|_| {
+                            if pseudo_random(&mut seed) % 10 < density {
+                                '@'
+                            } else {
+                                '.'
+                            }
+                        })
+                        .collect()
+                })
+                .collect();
+            let grid = grid.join("\n");
+
+            for adjacency in [Adjacency::All, Adjacency::Orthogonal] {
+                for threshold in 0..=5 {
+                    let mut rolls = parse_grid(&grid);
+                    assert_eq!(
+                        remove_rolls(&mut rolls, threshold, adjacency),
+                        rescanning_rounds(&grid, threshold, adjacency),
+                        "{grid}\nthreshold {threshold}, {adjacency:?}"
+                    );
+                }
+            }
+            assert_eq!(
+                removal_rounds(&grid),
+                rescanning_rounds(&grid, 4, Adjacency::All)
+            );
+        }
+    }
+
+    #[test]
+    fn large_grid_is_peeled_quickly() {
+        let mut seed = 2000;
+        let grid: Vec<String> = (0..2000)
+            .map(|_| {
+                (0..2000)
+                    .map(|_| {
+                        if pseudo_random(&mut seed).is_multiple_of(4) {
+                            '.'
+                        } else {
+                            '@'
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        let grid = grid.join("\n");
+
+        let (removed, remaining) = remove_all_accessible(&grid);
+
+        assert!(removed > 0);
+        assert!(find_accessible_positions(&remaining, 4, Adjacency::All).is_empty());
+    }
+
+    #[test]
+    fn short_last_line_is_padded_with_empty_cells() {
+        // The last line was trimmed to "@": the block above keeps its counts
+        let padded = "@@@\n@@@\n@..";
+        let trimmed = "@@@\n@@@\n@";
+        assert_eq!(parse_grid(trimmed), parse_grid(padded));
+        assert_eq!(
+            count_accessible_rolls(trimmed),
+            count_accessible_rolls(padded)
+        );
+        assert_eq!(
+            count_total_removable_rolls(trimmed),
+            count_total_removable_rolls(padded)
+        );
+        assert_eq!(remove_all_accessible(trimmed).1.concat().len(), 9);
+    }
+
+    #[test]
+    fn crlf_line_endings_are_ignored() {
+        let grid = "\
+..@@.@@@@.
+@@@.@.@.@@
+@@@@@.@.@@
+@.@@@@..@.
+@@.@@@@.@@
+.@@@@@@@.@
+.@.@.@.@@@
+@.@@@.@@@@
+.@@@@@@@@.
+@.@.@@@.@.";
+        let crlf = grid.replace('\n', "\r\n") + "\r\n";
+        assert_eq!(count_accessible_rolls(&crlf), 13);
+        assert_eq!(count_total_removable_rolls(&crlf), 43);
+        assert_eq!(parse_grid(&crlf), parse_grid(grid));
+        // A stray \r with no \n after it is whitespace: an empty cell
+        assert_eq!(parse_grid("@\r@\n@@@"), parse_grid("@.@\n@@@"));
+    }
+
+    #[test]
+    fn stray_character_is_an_error() {
+        let grid = "@@.\n.x@\n@@@";
+        let error = GridParseError {
+            character: 'x',
+            row: 1,
+            col: 1,
+        };
+        assert_eq!(try_count_accessible_rolls(grid), Err(error.clone()));
+        assert_eq!(try_count_total_removable_rolls(grid), Err(error.clone()));
+        assert_eq!(error.to_string(), "invalid cell 'x' at row 1, column 1");
+        assert_eq!(try_count_accessible_rolls("@@.\n.@@"), Ok(4));
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid grid: invalid cell 'x' at row 1, column 1")]
+    fn count_accessible_rolls_panics_on_stray_character() {
+        count_accessible_rolls("@@.\n.x@\n@@@");
+    }
 }
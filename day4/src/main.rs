@@ -0,0 +1,10 @@
+use day4::{count_accessible_rolls, count_total_removable_rolls};
+use std::fs;
+
+fn main() {
+    let input = fs::read_to_string("paper-roll-locations.txt").expect("Failed to read input file");
+    let result = count_accessible_rolls(&input);
+    println!("Part 1 Answer: {}", result);
+    let result_part2 = count_total_removable_rolls(&input);
+    println!("Part 2 Answer: {}", result_part2);
+}
@@ -1,3 +1,4 @@
+use std::fmt;
 use std::str::FromStr;
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -10,14 +11,33 @@ impl Range {
     pub fn contains(&self, id: u64) -> bool {
         id >= self.start && id <= self.end
     }
+
+    /// The first ID of the range.
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    /// The last ID of the range, included.
+    pub fn end(&self) -> u64 {
+        self.end
+    }
+}
+
+/// Writes the range as `start-end`, the form [`Range::from_str`] reads.
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
 }
 
+/// Splits `start-end`; a lone `N` stands for the range `N-N`.
 fn split_range_parts(s: &str) -> Result<(&str, &str), String> {
     let parts: Vec<&str> = s.split('-').collect();
-    if parts.len() != 2 {
-        return Err(format!("Invalid range format: {}", s));
+    match parts[..] {
+        [id] => Ok((id, id)),
+        [start, end] => Ok((start, end)),
+        _ => Err(format!("Invalid range format: {}", s)),
     }
-    Ok((parts[0], parts[1]))
 }
 
 fn parse_number_part(part: &str, part_name: &str) -> Result<u64, String> {
@@ -40,11 +60,18 @@ pub fn is_fresh(ranges: &[Range], id: u64) -> bool {
     ranges.iter().any(|range| range.contains(id))
 }
 
+/// Lazily yields the fresh IDs among `ids`, in their original order.
+pub fn fresh_ids<'a>(ranges: &'a [Range], ids: &'a [u64]) -> impl Iterator<Item = u64> + 'a {
+    ids.iter().copied().filter(|&id| is_fresh(ranges, id))
+}
+
 pub fn count_fresh(ranges: &[Range], ids: &[u64]) -> usize {
-    ids.iter().filter(|&&id| is_fresh(ranges, id)).count()
+    fresh_ids(ranges, ids).count()
 }
 
-fn merge_ranges(ranges: &[Range]) -> Vec<Range> {
+/// Sorts the ranges by start and coalesces overlapping or adjacent ones
+/// (`3-5` and `6-8` become `3-8`) into disjoint ranges.
+pub fn merge_ranges(ranges: &[Range]) -> Vec<Range> {
     if ranges.is_empty() {
         return Vec::new();
     }
@@ -67,15 +94,58 @@ fn merge_ranges(ranges: &[Range]) -> Vec<Range> {
     merged
 }
 
+/// The IDs between the lowest start and the highest end that no range covers,
+/// as sorted disjoint ranges.
+pub fn gaps(ranges: &[Range]) -> Vec<Range> {
+    merge_ranges(ranges)
+        .windows(2)
+        .map(|pair| Range {
+            start: pair[0].end + 1,
+            end: pair[1].start - 1,
+        })
+        .collect()
+}
+
+/// Disjoint, sorted ranges answering membership queries by binary search.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RangeSet {
+    ranges: Vec<Range>,
+}
+
+impl RangeSet {
+    pub fn from_ranges(ranges: Vec<Range>) -> Self {
+        RangeSet {
+            ranges: merge_ranges(&ranges),
+        }
+    }
+
+    /// Same answer as [`is_fresh`] on the ranges the set was built from.
+    pub fn contains(&self, id: u64) -> bool {
+        let after = self.ranges.partition_point(|range| range.start <= id);
+        after > 0 && self.ranges[after - 1].contains(id)
+    }
+}
+
 fn range_size(range: &Range) -> usize {
     (range.end - range.start + 1) as usize
 }
 
+/// Number of distinct IDs covered by `ranges`, overlaps counted once.
+/// Saturates at `u64::MAX` in the one case that does not fit: every `u64`.
+pub fn total_covered(ranges: &[Range]) -> u64 {
+    merge_ranges(ranges).iter().fold(0u64, |total, range| {
+        total
+            .saturating_add(range.end - range.start)
+            .saturating_add(1)
+    })
+}
+
 pub fn count_all_fresh_ids(ranges: &[Range]) -> usize {
     let merged = merge_ranges(ranges);
     merged.iter().map(range_size).sum()
 }
 
+/// Part 1: how many of the listed IDs are fresh, i.e. fall in at least one range.
 pub fn solve(input: &str) -> Result<usize, String> {
     let (ranges, ids) = parse_input(input)?;
     Ok(count_fresh(&ranges, &ids))
@@ -89,6 +159,8 @@ fn parse_ranges_from_input(input: &str) -> Result<Vec<Range>, String> {
     parse_ranges(parts[0])
 }
 
+/// Part 2: how many distinct IDs the ranges consider fresh. The listed IDs
+/// are ignored.
 pub fn solve_part2(input: &str) -> Result<usize, String> {
     let ranges = parse_ranges_from_input(input)?;
     Ok(count_all_fresh_ids(&ranges))
@@ -126,11 +198,11 @@ mod tests {
     #[test]
     fn range_contains_id_when_id_is_within_range() {
         let range = Range { start: 3, end: 5 };
-        assert_eq!(range.contains(3), true);
-        assert_eq!(range.contains(4), true);
-        assert_eq!(range.contains(5), true);
-        assert_eq!(range.contains(2), false);
-        assert_eq!(range.contains(6), false);
+        assert!(range.contains(3));
+        assert!(range.contains(4));
+        assert!(range.contains(5));
+        assert!(!range.contains(2));
+        assert!(!range.contains(6));
     }
 
     #[test]
@@ -142,10 +214,10 @@ mod tests {
     #[test]
     fn id_is_fresh_when_in_any_range() {
         let ranges = vec![Range { start: 3, end: 5 }, Range { start: 10, end: 14 }];
-        assert_eq!(is_fresh(&ranges, 5), true);
-        assert_eq!(is_fresh(&ranges, 11), true);
-        assert_eq!(is_fresh(&ranges, 1), false);
-        assert_eq!(is_fresh(&ranges, 8), false);
+        assert!(is_fresh(&ranges, 5));
+        assert!(is_fresh(&ranges, 11));
+        assert!(!is_fresh(&ranges, 1));
+        assert!(!is_fresh(&ranges, 8));
     }
 
     #[test]
@@ -201,4 +273,158 @@ mod tests {
         assert_eq!(merged[0], Range { start: 3, end: 5 });
         assert_eq!(merged[1], Range { start: 10, end: 20 });
     }
+
+    #[test]
+    fn solves_small_example() {
+        let input = "3-5\n10-14\n\n1\n5\n8";
+        // Only 5 is fresh; the ranges cover 3 + 5 IDs
+        assert_eq!(solve(input), Ok(1));
+        assert_eq!(solve_part2(input), Ok(8));
+    }
+
+    #[test]
+    fn merges_nested_ranges() {
+        let ranges = vec![Range { start: 1, end: 20 }, Range { start: 4, end: 9 }];
+        assert_eq!(merge_ranges(&ranges), vec![Range { start: 1, end: 20 }]);
+    }
+
+    #[test]
+    fn merges_touching_ranges() {
+        let ranges = vec![
+            Range { start: 6, end: 8 },
+            Range { start: 3, end: 5 },
+            Range { start: 4, end: 9 },
+        ];
+        assert_eq!(merge_ranges(&ranges), vec![Range { start: 3, end: 9 }]);
+        let ranges = vec![Range { start: 3, end: 5 }, Range { start: 6, end: 8 }];
+        assert_eq!(merge_ranges(&ranges), vec![Range { start: 3, end: 8 }]);
+    }
+
+    #[test]
+    fn keeps_disjoint_ranges_sorted() {
+        let ranges = vec![Range { start: 10, end: 14 }, Range { start: 3, end: 5 }];
+        assert_eq!(
+            merge_ranges(&ranges),
+            vec![Range { start: 3, end: 5 }, Range { start: 10, end: 14 }]
+        );
+        assert_eq!(merge_ranges(&[]), Vec::new());
+    }
+
+    #[test]
+    fn range_set_agrees_with_is_fresh() {
+        let ranges = vec![
+            Range { start: 3, end: 5 },
+            Range { start: 10, end: 14 },
+            Range { start: 16, end: 20 },
+            Range { start: 12, end: 18 },
+            Range { start: 30, end: 30 },
+        ];
+        let set = RangeSet::from_ranges(ranges.clone());
+        for id in 0..40 {
+            assert_eq!(set.contains(id), is_fresh(&ranges, id), "{}", id);
+        }
+    }
+
+    #[test]
+    fn empty_range_set_contains_nothing() {
+        let set = RangeSet::from_ranges(Vec::new());
+        assert!(!set.contains(0));
+        assert!(!set.contains(u64::MAX));
+    }
+
+    #[test]
+    fn total_covered_sums_disjoint_ranges() {
+        let ranges = vec![Range { start: 3, end: 5 }, Range { start: 10, end: 14 }];
+        assert_eq!(total_covered(&ranges), 8);
+    }
+
+    #[test]
+    fn total_covered_counts_overlap_once() {
+        let ranges = vec![Range { start: 3, end: 5 }, Range { start: 4, end: 9 }];
+        assert_eq!(total_covered(&ranges), 7);
+    }
+
+    #[test]
+    fn total_covered_does_not_overflow() {
+        let ranges = vec![
+            Range {
+                start: 0,
+                end: u64::MAX / 2,
+            },
+            Range {
+                start: u64::MAX / 2 + 2,
+                end: u64::MAX,
+            },
+        ];
+        assert_eq!(total_covered(&ranges), u64::MAX);
+    }
+
+    #[test]
+    fn single_number_parses_as_one_id_range() {
+        assert_eq!("7".parse::<Range>(), Ok(Range { start: 7, end: 7 }));
+    }
+
+    #[test]
+    fn range_with_too_many_dashes_is_rejected() {
+        assert_eq!(
+            "1-2-3".parse::<Range>(),
+            Err("Invalid range format: 1-2-3".to_string())
+        );
+    }
+
+    #[test]
+    fn single_ids_mix_with_ranges_in_input() {
+        let input = "3-5\n8\n\n1\n5\n8\n9";
+        assert_eq!(solve(input), Ok(2));
+        assert_eq!(solve_part2(input), Ok(4));
+    }
+
+    #[test]
+    fn gaps_between_disjoint_ranges() {
+        let ranges = vec![Range { start: 10, end: 14 }, Range { start: 3, end: 5 }];
+        assert_eq!(gaps(&ranges), vec![Range { start: 6, end: 9 }]);
+    }
+
+    #[test]
+    fn no_gaps_between_adjacent_or_overlapping_ranges() {
+        let ranges = vec![
+            Range { start: 3, end: 5 },
+            Range { start: 6, end: 8 },
+            Range { start: 7, end: 12 },
+        ];
+        assert_eq!(gaps(&ranges), Vec::new());
+    }
+
+    #[test]
+    fn gaps_and_merged_ranges_are_readable_through_accessors() {
+        let ranges: Vec<Range> = ["10-14", "3-5", "16-20", "12-18", "25-30"]
+            .iter()
+            .map(|range| range.parse().unwrap())
+            .collect();
+
+        let merged = merge_ranges(&ranges);
+        let bounds: Vec<(u64, u64)> = merged.iter().map(|r| (r.start(), r.end())).collect();
+        assert_eq!(bounds, vec![(3, 5), (10, 20), (25, 30)]);
+
+        let gap = gaps(&ranges);
+        let written: Vec<String> = gap.iter().map(Range::to_string).collect();
+        assert_eq!(written, vec!["6-9", "21-24"]);
+        assert_eq!(written[1].parse::<Range>(), Ok(gap[1]));
+    }
+
+    #[test]
+    fn single_range_has_no_gaps() {
+        assert_eq!(gaps(&[Range { start: 3, end: 5 }]), Vec::new());
+        assert_eq!(gaps(&[]), Vec::new());
+    }
+
+    #[test]
+    fn fresh_ids_keeps_input_order() {
+        let input = "3-5\n10-14\n16-20\n12-18\n\n17\n1\n5\n8\n11\n32";
+        let (ranges, ids) = parse_input(input).unwrap();
+        assert_eq!(
+            fresh_ids(&ranges, &ids).collect::<Vec<_>>(),
+            vec![17, 5, 11]
+        );
+    }
 }
@@ -1,25 +1,73 @@
+/// Like [`try_solve`], for worksheets known not to overflow.
 pub fn solve(input: &str) -> u64 {
-    let lines: Vec<&str> = input.lines().filter(|l| !l.is_empty()).collect();
+    try_solve(input).expect("worksheet overflows u64")
+}
+
+/// Grand total of the worksheet, or an error naming the first problem whose
+/// result does not fit in a `u64`.
+pub fn try_solve(input: &str) -> Result<u64, String> {
+    let lines = worksheet_lines(input);
     if lines.is_empty() {
-        return 0;
+        return Ok(0);
     }
 
     let problem_boundaries = find_problem_boundaries(&lines);
-    problem_boundaries
+    grand_total(
+        problem_boundaries
+            .iter()
+            .map(|(start, end)| solve_problem(&lines, *start, *end)),
+    )
+}
+
+/// The result of every problem of the worksheet, left to right, so that
+/// [`solve`] is their sum.
+///
+/// # Panics
+/// Panics if a problem overflows a `u64`; see [`try_solve_each`].
+pub fn solve_each(input: &str) -> Vec<u64> {
+    try_solve_each(input).expect("worksheet overflows u64")
+}
+
+/// Like [`solve_each`], or an error naming the first problem whose result does
+/// not fit in a `u64`.
+pub fn try_solve_each(input: &str) -> Result<Vec<u64>, String> {
+    let lines = worksheet_lines(input);
+    if lines.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    find_problem_boundaries(&lines)
         .iter()
         .map(|(start, end)| solve_problem(&lines, *start, *end))
-        .sum()
+        .collect()
 }
 
-fn find_problem_boundaries(lines: &[&str]) -> Vec<(usize, usize)> {
-    let num_data_lines = lines.len() - 1;
+/// The non-empty lines of the worksheet, each split into its chars once so
+/// columns can be indexed directly.
+fn worksheet_lines(input: &str) -> Vec<Vec<char>> {
+    input
+        .lines()
+        .filter(|l| !l.is_empty())
+        .map(|l| l.chars().collect())
+        .collect()
+}
+
+fn grand_total(mut results: impl Iterator<Item = Result<u64, String>>) -> Result<u64, String> {
+    results.try_fold(0u64, |total, result| {
+        total
+            .checked_add(result?)
+            .ok_or_else(|| "overflow in grand total".to_string())
+    })
+}
+
+fn find_problem_boundaries(lines: &[Vec<char>]) -> Vec<(usize, usize)> {
     let max_width = lines.iter().map(|l| l.len()).max().unwrap_or(0);
 
     let mut boundaries = Vec::new();
     let mut problem_start = None;
 
     for col in 0..=max_width {
-        let is_separator = is_separator_column(lines, col, num_data_lines);
+        let is_separator = is_separator_column(lines, col);
 
         match (is_separator, problem_start) {
             (false, None) => problem_start = Some(col),
@@ -38,121 +86,152 @@ fn find_problem_boundaries(lines: &[&str]) -> Vec<(usize, usize)> {
     boundaries
 }
 
-fn is_separator_column(lines: &[&str], col: usize, num_data_lines: usize) -> bool {
-    (0..num_data_lines)
-        .all(|row| col >= lines[row].len() || lines[row].chars().nth(col).unwrap_or(' ') == ' ')
+/// A column is a gap between problems when every line, the operator line
+/// included, has whitespace there (a space or a tab) or is too short to reach
+/// it. An operator sitting over a gap in the numbers starts a new problem.
+fn is_separator_column(lines: &[Vec<char>], col: usize) -> bool {
+    lines
+        .iter()
+        .all(|line| line.get(col).is_none_or(|ch| ch.is_whitespace()))
 }
 
-fn solve_problem(lines: &[&str], start_col: usize, end_col: usize) -> u64 {
+fn solve_problem(lines: &[Vec<char>], start_col: usize, end_col: usize) -> Result<u64, String> {
     let num_data_lines = lines.len() - 1;
-    let op_line = lines[num_data_lines];
+    let op_line = &lines[num_data_lines];
 
     let operation = extract_operation(op_line, start_col, end_col);
-    let numbers = extract_numbers_from_problem(lines, start_col, end_col, num_data_lines);
-
-    apply_operation(&numbers, operation)
+    extract_numbers_from_problem(lines, start_col, end_col, num_data_lines)
+        .and_then(|numbers| apply_operation(&numbers, operation))
+        .ok_or_else(|| format!("overflow in column starting at col {start_col}"))
 }
 
-fn extract_operation(op_line: &str, start_col: usize, end_col: usize) -> char {
-    op_line
-        .chars()
-        .skip(start_col)
-        .take(end_col - start_col)
-        .find(|&ch| ch == '+' || ch == '*')
+const OPERATORS: [char; 4] = ['+', '*', '-', '/'];
+
+fn extract_operation(op_line: &[char], start_col: usize, end_col: usize) -> char {
+    row_slice(op_line, start_col, end_col)
+        .iter()
+        .copied()
+        .find(|ch| OPERATORS.contains(ch))
         .unwrap_or(' ')
 }
 
+/// The numbers of the problem, top to bottom, or `None` if one does not fit
+/// in a `u64`.
 fn extract_numbers_from_problem(
-    lines: &[&str],
+    lines: &[Vec<char>],
     start_col: usize,
     end_col: usize,
     num_data_lines: usize,
-) -> Vec<u64> {
-    (0..num_data_lines)
-        .filter_map(|row| {
-            let row_slice = extract_row_slice(lines[row], start_col, end_col);
-            parse_number_from_slice(&row_slice)
-        })
+) -> Option<Vec<u64>> {
+    lines[..num_data_lines]
+        .iter()
+        .filter_map(|line| parse_number_from_slice(row_slice(line, start_col, end_col)))
         .collect()
 }
 
-fn extract_row_slice(line: &str, start_col: usize, end_col: usize) -> String {
-    line.chars()
-        .skip(start_col)
-        .take(end_col.saturating_sub(start_col))
-        .collect()
+/// The cells of `line` in `start_col..end_col`, cut short where the line ends.
+fn row_slice(line: &[char], start_col: usize, end_col: usize) -> &[char] {
+    let end_col = end_col.min(line.len());
+    line.get(start_col..end_col).unwrap_or(&[])
 }
 
-fn parse_number_from_slice(slice: &str) -> Option<u64> {
-    let digits: String = slice.chars().filter(|ch| ch.is_ascii_digit()).collect();
+/// The number written in `slice`: `None` without digits, `Some(None)` when it
+/// is too large for a `u64`.
+fn parse_number_from_slice(slice: &[char]) -> Option<Option<u64>> {
+    let digits: String = slice.iter().filter(|ch| ch.is_ascii_digit()).collect();
+    parse_digits(&digits)
+}
+
+fn parse_digits(digits: &str) -> Option<Option<u64>> {
     if digits.is_empty() {
         None
     } else {
-        digits.parse().ok()
+        Some(digits.parse().ok())
     }
 }
 
-fn apply_operation(numbers: &[u64], operation: char) -> u64 {
+/// Folds `numbers` with `operation`, top to bottom, or `None` if a sum or a
+/// product overflows `u64` or a difference goes below zero. A division by
+/// zero gives 0.
+fn apply_operation(numbers: &[u64], operation: char) -> Option<u64> {
     match operation {
-        '+' => numbers.iter().sum(),
-        '*' => numbers.iter().product(),
-        _ => 0,
+        '+' => numbers.iter().try_fold(0u64, |acc, &n| acc.checked_add(n)),
+        '*' => numbers.iter().try_fold(1u64, |acc, &n| acc.checked_mul(n)),
+        '-' => try_fold_left(numbers, u64::checked_sub),
+        '/' => try_fold_left(numbers, |acc, n| Some(acc.checked_div(n).unwrap_or(0))),
+        _ => Some(0),
+    }
+}
+
+fn try_fold_left(numbers: &[u64], f: impl Fn(u64, u64) -> Option<u64>) -> Option<u64> {
+    match numbers.split_first() {
+        Some((&first, rest)) => rest.iter().try_fold(first, |acc, &n| f(acc, n)),
+        None => Some(0),
     }
 }
 
+/// Part 2 reads cephalopod math: within each problem every column is one
+/// number, its digits top to bottom, and the problem's operator is applied to
+/// those column numbers. Problems are read right to left; the grand total is
+/// the same either way.
 pub fn solve_part2(input: &str) -> u64 {
-    let lines: Vec<&str> = input.lines().filter(|l| !l.is_empty()).collect();
+    try_solve_part2(input).expect("worksheet overflows u64")
+}
+
+/// Part 2 counterpart of [`try_solve`].
+pub fn try_solve_part2(input: &str) -> Result<u64, String> {
+    let lines = worksheet_lines(input);
     if lines.is_empty() {
-        return 0;
+        return Ok(0);
     }
 
     let problem_boundaries = find_problem_boundaries(&lines);
     // Read problems right-to-left (reverse order)
-    problem_boundaries
-        .iter()
-        .rev()
-        .map(|(start, end)| solve_problem_part2(&lines, *start, *end))
-        .sum()
+    grand_total(
+        problem_boundaries
+            .iter()
+            .rev()
+            .map(|(start, end)| solve_problem_part2(&lines, *start, *end)),
+    )
 }
 
-fn solve_problem_part2(lines: &[&str], start_col: usize, end_col: usize) -> u64 {
+fn solve_problem_part2(
+    lines: &[Vec<char>],
+    start_col: usize,
+    end_col: usize,
+) -> Result<u64, String> {
     let num_data_lines = lines.len() - 1;
-    let op_line = lines[num_data_lines];
+    let op_line = &lines[num_data_lines];
 
     let operation = extract_operation(op_line, start_col, end_col);
-    let numbers = extract_numbers_from_problem_part2(lines, start_col, end_col, num_data_lines);
-
-    apply_operation(&numbers, operation)
+    extract_numbers_from_problem_part2(lines, start_col, end_col, num_data_lines)
+        .and_then(|numbers| apply_operation(&numbers, operation))
+        .ok_or_else(|| format!("overflow in column starting at col {start_col}"))
 }
 
+/// The column numbers of the problem, or `None` if one does not fit in a `u64`.
 fn extract_numbers_from_problem_part2(
-    lines: &[&str],
+    lines: &[Vec<char>],
     start_col: usize,
     end_col: usize,
     num_data_lines: usize,
-) -> Vec<u64> {
+) -> Option<Vec<u64>> {
     (start_col..end_col)
         .filter_map(|col| read_number_from_column(lines, col, num_data_lines))
         .collect()
 }
 
-fn read_number_from_column(lines: &[&str], col: usize, num_data_lines: usize) -> Option<u64> {
-    let digits: String = (0..num_data_lines)
-        .filter_map(|row| {
-            if col < lines[row].len() {
-                let ch = lines[row].chars().nth(col)?;
-                if ch.is_ascii_digit() { Some(ch) } else { None }
-            } else {
-                None
-            }
-        })
+/// Like [`parse_number_from_slice`], reading the digits of column `col`.
+fn read_number_from_column(
+    lines: &[Vec<char>],
+    col: usize,
+    num_data_lines: usize,
+) -> Option<Option<u64>> {
+    let digits: String = lines[..num_data_lines]
+        .iter()
+        .filter_map(|line| line.get(col).filter(|ch| ch.is_ascii_digit()))
         .collect();
-
-    if digits.is_empty() {
-        None
-    } else {
-        digits.parse().ok()
-    }
+    parse_digits(&digits)
 }
 
 #[cfg(test)]
@@ -179,4 +258,134 @@ mod tests {
         let result = solve_part2(input);
         assert_eq!(result, 3263827);
     }
+
+    #[test]
+    fn solve_part2_single_problem_reads_columns() {
+        // Columns read top to bottom: 1, 24, 356
+        let input = "123\n 45\n  6\n*\n";
+        assert_eq!(solve_part2(input), 8544);
+    }
+
+    #[test]
+    fn subtraction_folds_top_to_bottom() {
+        // 100 - 30 - 5, next to an addition problem
+        let input = "100 1\n 30 2\n  5 3\n-   +\n";
+        assert_eq!(solve(input), 65 + 6);
+        assert_eq!(solve("9\n9\n-\n"), 0);
+    }
+
+    #[test]
+    fn subtraction_below_zero_is_reported() {
+        // 3 - 5 has no unsigned result; the problem starts at col 2
+        let input = "1 3\n1 5\n+ -\n";
+        assert_eq!(
+            try_solve(input),
+            Err("overflow in column starting at col 2".to_string())
+        );
+        // Columns 1, 5, 1 read the same either way, and 1 - 5 is below zero
+        assert!(try_solve_part2("151\n-\n").is_err());
+    }
+
+    #[test]
+    fn division_folds_top_to_bottom() {
+        // 100 / 7 / 2 in integer division
+        let input = "100\n  7\n  2\n/  \n";
+        assert_eq!(solve(input), 7);
+        assert_eq!(solve("8\n0\n/\n"), 0);
+    }
+
+    #[test]
+    fn overflowing_product_is_reported() {
+        // 10^10 * 10^10 does not fit in u64; the second problem starts at col 2
+        let input = "1 10000000000\n1 10000000000\n+ *          \n";
+        assert_eq!(
+            try_solve(input),
+            Err("overflow in column starting at col 2".to_string())
+        );
+        assert_eq!(
+            try_solve("4294967296\n4294967295\n*\n"),
+            Ok(18446744069414584320)
+        );
+        assert!(try_solve("18446744073709551615\n1\n+\n").is_err());
+    }
+
+    #[test]
+    fn number_too_large_for_u64_is_reported() {
+        let input = "99999999999999999999\n1\n+\n";
+        assert_eq!(
+            try_solve(input),
+            Err("overflow in column starting at col 0".to_string())
+        );
+        // Part 2 reads the twenty columns 91, 9, 9, ... which do fit
+        assert_eq!(try_solve_part2(input), Ok(91 + 19 * 9));
+        // A column of twenty digits does not
+        let tall = "9\n".repeat(20) + "+\n";
+        assert_eq!(
+            try_solve_part2(&tall),
+            Err("overflow in column starting at col 0".to_string())
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "worksheet overflows u64")]
+    fn solve_panics_on_overflow() {
+        solve("10000000000\n10000000000\n*\n");
+    }
+
+    #[test]
+    fn solve_each_lists_problems_left_to_right() {
+        let input = "123 328  51 64 \n 45 64  387 23 \n  6 98  215 314\n*   +   *   +  \n";
+        let results = solve_each(input);
+        assert_eq!(results, vec![33210, 490, 4243455, 401]);
+        assert_eq!(results.iter().sum::<u64>(), solve(input));
+        assert!(solve_each("").is_empty());
+    }
+
+    #[test]
+    fn try_solve_each_reports_the_overflowing_problem() {
+        let input = "1 10000000000\n1 10000000000\n+ *          \n";
+        assert_eq!(
+            try_solve_each(input),
+            Err("overflow in column starting at col 2".to_string())
+        );
+        assert_eq!(try_solve_each("2 3\n4 5\n* +\n"), Ok(vec![8, 8]));
+    }
+
+    #[test]
+    #[should_panic(expected = "worksheet overflows u64")]
+    fn solve_each_panics_on_overflow() {
+        solve_each("10000000000\n10000000000\n*\n");
+    }
+
+    #[test]
+    fn tabs_separate_problems_like_spaces() {
+        let spaced = "123 328  51 64 \n 45 64  387 23 \n  6 98  215 314\n*   +   *   +  \n";
+        // Columns 3, 7 and 11 separate the problems; write them as tabs
+        let tabbed: String = spaced
+            .lines()
+            .map(|line| {
+                let row: String = line
+                    .char_indices()
+                    .map(|(col, ch)| if [3, 7, 11].contains(&col) { '\t' } else { ch })
+                    .collect();
+                row + "\n"
+            })
+            .collect();
+        assert!(tabbed.starts_with("123\t328\t 51\t64 \n"));
+
+        assert_eq!(solve(&tabbed), solve(spaced));
+        assert_eq!(solve_each(&tabbed), solve_each(spaced));
+        assert_eq!(solve_part2(&tabbed), solve_part2(spaced));
+    }
+
+    #[test]
+    fn operator_over_a_gap_starts_a_new_problem() {
+        // Columns 2 and 3 are blank in the numbers, but `*` sits in column 3:
+        // only column 2 separates `12 + 34` from the narrow `5 * 6`
+        let input = "12  5\n34  6\n+  *\n";
+        assert_eq!(solve_each(input), vec![46, 30]);
+        assert_eq!(solve(input), 76);
+        // Part 2 reads 13 + 24 and the single column 56
+        assert_eq!(solve_part2(input), 37 + 56);
+    }
 }
@@ -4,8 +4,14 @@ pub struct Point {
     pub y: usize,
 }
 
+/// The glyph that splits beams unless another one is configured.
+pub const DEFAULT_SPLITTER: char = '^';
+
 pub struct Grid {
+    /// The first beam source, in reading order.
     pub start: Point,
+    /// Every beam source, in reading order.
+    pub starts: Vec<Point>,
     pub cells: Vec<Vec<char>>,
     pub width: usize,
     pub height: usize,
@@ -15,19 +21,25 @@ pub struct Grid {
 pub enum Interaction {
     Continue(Point),
     Split(Option<Point>, Option<Point>),
+    /// Sent one step sideways by a mirror, then falling again; `None` when the
+    /// mirror faces the edge of the grid.
+    Deflected(Option<Point>),
     Terminated,
 }
 
 impl Grid {
+    /// The cell at `p`, or `None` outside the grid. Rows may be shorter than
+    /// `width`; past the end of its own row a cell is outside too.
     pub fn get(&self, p: &Point) -> Option<char> {
-        if p.y < self.height && p.x < self.width {
-            Some(self.cells[p.y][p.x])
-        } else {
-            None
-        }
+        self.cells.get(p.y)?.get(p.x).copied()
     }
 
     pub fn interact(&self, p: &Point) -> Interaction {
+        self.interact_with_splitter(p, DEFAULT_SPLITTER)
+    }
+
+    /// Like [`Grid::interact`], with `splitter` as the glyph that splits beams.
+    pub fn interact_with_splitter(&self, p: &Point, splitter: char) -> Interaction {
         let next_y = p.y + 1;
         if next_y >= self.height {
             return Interaction::Terminated;
@@ -35,7 +47,7 @@ impl Grid {
 
         let next_pos = Point { x: p.x, y: next_y };
         match self.get(&next_pos) {
-            Some('^') => {
+            Some(c) if c == splitter => {
                 let left = if next_pos.x > 0 {
                     Some(Point {
                         x: next_pos.x - 1,
@@ -54,6 +66,18 @@ impl Grid {
                 };
                 Interaction::Split(left, right)
             }
+            // `/` sends a falling beam to the left, `\` to the right
+            Some('/') => Interaction::Deflected(
+                next_pos
+                    .x
+                    .checked_sub(1)
+                    .map(|x| Point { x, y: next_pos.y }),
+            ),
+            Some('\\') => Interaction::Deflected(
+                Some(next_pos.x + 1)
+                    .filter(|&x| x < self.width)
+                    .map(|x| Point { x, y: next_pos.y }),
+            ),
             Some(_) => Interaction::Continue(next_pos),
             None => Interaction::Terminated,
         }
@@ -71,7 +95,7 @@ impl std::str::FromStr for Grid {
         let height = cells.len();
         let width = cells[0].len();
 
-        let start = cells
+        let starts: Vec<Point> = cells
             .iter()
             .enumerate()
             .flat_map(|(y, row)| {
@@ -79,12 +103,17 @@ impl std::str::FromStr for Grid {
                     .enumerate()
                     .map(move |(x, c)| (Point { x, y }, c))
             })
-            .find(|(_, c)| **c == 'S')
+            .filter(|(_, c)| **c == 'S')
             .map(|(point, _)| point)
+            .collect();
+        let start = starts
+            .first()
+            .cloned()
             .ok_or_else(|| "Start point 'S' not found".to_string())?;
 
         Ok(Grid {
             start,
+            starts,
             cells,
             width,
             height,
@@ -99,22 +128,56 @@ pub fn parse(input: &str) -> Grid {
 
 struct Simulation {
     grid: Grid,
+    splitter: char,
+    /// The beams on the row the sweep has reached, sorted and distinct.
     beams: Vec<Point>,
+    row: usize,
+    /// Sources on rows the sweep has not reached yet, in reading order.
+    sources: Vec<Point>,
     splits: u64,
+    /// Splits counted on the row of the splitter that caused them.
+    splits_by_row: Vec<u64>,
+    /// Every cell a beam has occupied so far.
+    energized: HashSet<Point>,
 }
 
 impl Simulation {
     fn new(grid: Grid) -> Self {
-        let beams = vec![grid.start.clone()];
-        Self {
+        Self::with_splitter(grid, DEFAULT_SPLITTER)
+    }
+
+    fn with_splitter(grid: Grid, splitter: char) -> Self {
+        let row = grid.start.y;
+        let sources = grid.starts.clone();
+        let splits_by_row = vec![0; grid.height];
+        let mut simulation = Self {
             grid,
-            beams,
+            splitter,
+            beams: Vec::new(),
+            row,
+            sources,
             splits: 0,
-        }
+            splits_by_row,
+            energized: HashSet::new(),
+        };
+        simulation.emit_sources();
+        simulation
+    }
+
+    /// Adds the sources of the current row to its beams. Every beam moves down
+    /// one row per step, so a beam reaching a source merges with it here.
+    fn emit_sources(&mut self) {
+        let remaining = self.sources.iter().position(|source| source.y != self.row);
+        let emitted = self
+            .sources
+            .drain(..remaining.unwrap_or(self.sources.len()));
+        self.beams.extend(emitted);
+        self.beams.sort();
+        self.beams.dedup();
     }
 
     fn run(&mut self) -> u64 {
-        while !self.beams.is_empty() {
+        while !self.beams.is_empty() || !self.sources.is_empty() {
             self.step();
         }
         self.splits
@@ -124,9 +187,11 @@ impl Simulation {
         let mut next_beams = Vec::new();
 
         for beam in &self.beams {
-            match self.grid.interact(beam) {
+            self.energized.insert(beam.clone());
+            match self.grid.interact_with_splitter(beam, self.splitter) {
                 Interaction::Split(left, right) => {
                     self.splits += 1;
+                    self.splits_by_row[beam.y + 1] += 1;
                     if let Some(p) = left {
                         next_beams.push(p);
                     }
@@ -134,69 +199,151 @@ impl Simulation {
                         next_beams.push(p);
                     }
                 }
-                Interaction::Continue(p) => {
+                Interaction::Continue(p) | Interaction::Deflected(Some(p)) => {
                     next_beams.push(p);
                 }
-                Interaction::Terminated => {}
+                Interaction::Deflected(None) | Interaction::Terminated => {}
             }
         }
-        next_beams.sort();
-        next_beams.dedup();
         self.beams = next_beams;
+        self.row += 1;
+        self.emit_sources();
     }
 }
 
+/// Part 1: the number of times a beam, starting at an `S` and falling down,
+/// is split by a `^`. Every `S` is a source; beams that meet, from the same
+/// source or not, are counted once.
 pub fn solve(input: &str) -> u64 {
     let grid = parse(input);
     let mut simulation = Simulation::new(grid);
     simulation.run()
 }
 
-use std::collections::HashMap;
+/// Like [`solve`], but reports an invalid grid instead of panicking.
+///
+/// Beams cannot loop: every step moves a beam one row down, a mirror's
+/// sideways deflection included, so the simulation ends once the last row is
+/// passed.
+pub fn try_solve(input: &str) -> Result<u64, String> {
+    let grid: Grid = input.parse()?;
+    let mut simulation = Simulation::new(grid);
+    Ok(simulation.run())
+}
+
+/// The number of splits on each grid row, counted on the row of the splitter;
+/// the rows add up to [`solve`].
+pub fn splits_per_row(input: &str) -> Vec<u64> {
+    let grid = parse(input);
+    let mut simulation = Simulation::new(grid);
+    simulation.run();
+    simulation.splits_by_row
+}
+
+/// The number of distinct cells beams pass through, sources included. A beam
+/// falling onto a splitter or mirror lands beside it instead, so that cell
+/// only counts when a beam sent sideways lands on it.
+pub fn energized_cells(input: &str) -> usize {
+    let grid = parse(input);
+    let mut simulation = Simulation::new(grid);
+    simulation.run();
+    simulation.energized.len()
+}
+
+/// Like [`solve`], with `splitter` in place of `^`.
+pub fn solve_with_splitter(input: &str, splitter: char) -> u64 {
+    let grid = parse(input);
+    let mut simulation = Simulation::with_splitter(grid, splitter);
+    simulation.run()
+}
+
+use std::collections::{HashMap, HashSet};
 
 struct PathCounter {
     grid: Grid,
+    splitter: char,
     memo: HashMap<Point, u64>,
 }
 
 impl PathCounter {
     fn new(grid: Grid) -> Self {
+        Self::with_splitter(grid, DEFAULT_SPLITTER)
+    }
+
+    fn with_splitter(grid: Grid, splitter: char) -> Self {
         Self {
             grid,
+            splitter,
             memo: HashMap::new(),
         }
     }
 
-    fn count(&mut self, p: Point) -> u64 {
+    /// Counts the paths from `start`, resolving the cells below it first with an
+    /// explicit work stack rather than recursion, so tall grids cannot overflow
+    /// the call stack.
+    fn count(&mut self, start: Point) -> u64 {
+        let mut stack = vec![start.clone()];
+        while let Some(p) = stack.last().cloned() {
+            if self.memo.contains_key(&p) {
+                stack.pop();
+                continue;
+            }
+
+            let next = self.next_points(&p);
+            let pending: Vec<Point> = next
+                .iter()
+                .flatten()
+                .filter(|q| !self.memo.contains_key(q))
+                .cloned()
+                .collect();
+            if pending.is_empty() {
+                let count = next
+                    .iter()
+                    .map(|q| q.as_ref().map_or(1, |q| self.memo[q]))
+                    .sum();
+                self.memo.insert(p, count);
+                stack.pop();
+            } else {
+                stack.extend(pending);
+            }
+        }
+        self.memo[&start]
+    }
+
+    /// Where a particle at `p` goes next; `None` is a path that ends there.
+    fn next_points(&self, p: &Point) -> Vec<Option<Point>> {
         // Check if we are already out of bounds (should be handled by caller, but for safety)
         if p.y >= self.grid.height || p.x >= self.grid.width {
-            return 1;
+            return vec![None];
         }
 
-        if let Some(&count) = self.memo.get(&p) {
-            return count;
+        match self.grid.interact_with_splitter(p, self.splitter) {
+            Interaction::Split(left, right) => vec![left, right],
+            Interaction::Continue(next_p) => vec![Some(next_p)],
+            // Like a split beam leaving the grid, a beam deflected into the
+            // edge is one finished path.
+            Interaction::Deflected(next_p) => vec![next_p],
+            Interaction::Terminated => vec![None],
         }
-
-        let count = match self.grid.interact(&p) {
-            Interaction::Split(left, right) => {
-                let left_count = left.map(|p| self.count(p)).unwrap_or(1);
-                let right_count = right.map(|p| self.count(p)).unwrap_or(1);
-                left_count + right_count
-            }
-            Interaction::Continue(next_p) => self.count(next_p),
-            Interaction::Terminated => 1,
-        };
-
-        self.memo.insert(p, count);
-        count
     }
 }
 
+/// Part 2: the number of distinct paths a single particle can take from `S`
+/// to the bottom, going left or right at every `^` it meets, summed over
+/// every `S`.
 pub fn solve_part2(input: &str) -> u64 {
     let grid = parse(input);
-    let start = grid.start.clone();
+    let starts = grid.starts.clone();
     let mut counter = PathCounter::new(grid);
-    counter.count(start)
+    starts.into_iter().map(|start| counter.count(start)).sum()
+}
+
+/// Like [`solve_part2`], with `splitter` in place of `^`.
+pub fn solve_part2_with_splitter(input: &str, splitter: char) -> u64 {
+    let grid = parse(input);
+    let starts = grid.starts.clone();
+    let mut counter = PathCounter::with_splitter(grid, splitter);
+    starts.into_iter().map(|start| counter.count(start)).sum()
 }
 
 #[cfg(test)]
@@ -269,4 +416,153 @@ mod tests {
         let input = include_str!("../puzzle-input.txt");
         assert_eq!(solve_part2(input), 8632253783011);
     }
+
+    #[test]
+    fn mirror_deflects_beam_into_the_wall() {
+        // `/` sends the beam left of column 0: it leaves the grid unsplit
+        let input = "S..\n/.^\n...";
+        assert_eq!(solve(input), 0);
+        assert_eq!(solve_part2(input), 1);
+    }
+
+    #[test]
+    fn mirror_deflects_beam_onto_a_splitter() {
+        // `\` moves the beam to column 2, where it falls onto the `^`
+        let input = ".S.\n.\\.\n..^\n...";
+        assert_eq!(solve(input), 1);
+        // One half keeps falling, the other leaves through the right edge
+        assert_eq!(solve_part2(input), 2);
+    }
+
+    #[test]
+    fn custom_splitter_reproduces_the_example() {
+        let input = ".......S.......
+...............
+.......^.......
+...............
+......^.^......
+...............
+.....^.^.^.....
+...............
+....^.^...^....
+...............
+...^.^...^.^...
+...............
+..^...^.....^..
+...............
+.^.^.^.^.^...^.
+..............."
+            .replace('^', "#");
+        assert_eq!(solve_with_splitter(&input, '#'), 21);
+        assert_eq!(solve_part2_with_splitter(&input, '#'), 40);
+        // With the default splitter the `#`s are empty space
+        assert_eq!(solve(&input), 0);
+        assert_eq!(solve_part2(&input), 1);
+    }
+
+    #[test]
+    fn parse_collects_every_start() {
+        let grid = parse("S..\n..S\n.S.");
+        assert_eq!(
+            grid.starts,
+            vec![
+                Point { x: 0, y: 0 },
+                Point { x: 2, y: 1 },
+                Point { x: 1, y: 2 }
+            ]
+        );
+        assert_eq!(grid.start, Point { x: 0, y: 0 });
+    }
+
+    #[test]
+    fn two_sources_add_their_splits() {
+        // The left source meets one splitter, the right one three
+        let input = ".S.....S..\n.^.....^..\n......^.^.\n..........";
+        assert_eq!(solve(input), 1 + 3);
+        // 2 paths on the left, 2 under each half of the right split
+        assert_eq!(solve_part2(input), 2 + 4);
+    }
+
+    #[test]
+    fn beam_reaching_a_lower_source_merges_with_it() {
+        // The upper beam passes the lower `S` and both hit the same splitter
+        let input = ".S.\n.S.\n.^.\n...";
+        assert_eq!(solve(input), 1);
+        assert_eq!(splits_per_row(input), vec![0, 0, 1, 0]);
+        assert_eq!(try_solve(input), Ok(1));
+        // Both sources, then the two halves of the split on rows 2 and 3
+        assert_eq!(energized_cells(input), 6);
+
+        // A lower source off the upper beam's column still adds its splits
+        let input = "S..\n..S\n^.^\n...";
+        assert_eq!(solve(input), 2);
+        assert_eq!(splits_per_row(input), vec![0, 0, 2, 0]);
+    }
+
+    #[test]
+    fn energized_cells_counts_every_touched_cell() {
+        // The source, then both halves of the split on rows 1 and 2
+        assert_eq!(energized_cells(".S.\n.^.\n..."), 5);
+        // A straight beam lights its whole column
+        assert_eq!(energized_cells(".S.\n...\n..."), 3);
+    }
+
+    #[test]
+    fn short_rows_end_the_beam_instead_of_panicking() {
+        // Row 1 stops before the beam's column
+        let input = "..S\n.\n...";
+        let grid = parse(input);
+        assert_eq!(grid.get(&Point { x: 2, y: 1 }), None);
+        assert_eq!(grid.get(&Point { x: 0, y: 1 }), Some('.'));
+        assert_eq!(solve(input), 0);
+        assert_eq!(solve_part2(input), 1);
+        assert_eq!(energized_cells(input), 1);
+    }
+
+    #[test]
+    fn try_solve_agrees_with_solve_on_the_puzzle_input() {
+        let input = include_str!("../puzzle-input.txt");
+        assert_eq!(try_solve(input), Ok(solve(input)));
+        assert_eq!(try_solve(""), Err("Empty grid".to_string()));
+    }
+
+    #[test]
+    fn mirror_pocket_does_not_trap_the_beam() {
+        // `\` sends the beam onto the `/`, from where it falls again: mirrors
+        // only deflect falling beams, so there is no loop to catch
+        let input = ".S..\n.\\/.\n....";
+        assert_eq!(try_solve(input), Ok(0));
+        // The source, the `/` the beam was sent onto, and the cell below it
+        assert_eq!(energized_cells(input), 3);
+    }
+
+    #[test]
+    fn splits_per_row_of_the_example() {
+        let input = ".......S.......
+...............
+.......^.......
+...............
+......^.^......
+...............
+.....^.^.^.....
+...............
+....^.^...^....
+...............
+...^.^...^.^...
+...............
+..^...^.....^..
+...............
+.^.^.^.^.^...^.
+...............";
+        let rows = splits_per_row(input);
+        assert_eq!(rows, vec![0, 0, 1, 0, 2, 0, 3, 0, 3, 0, 4, 0, 3, 0, 5, 0]);
+        assert_eq!(rows.iter().sum::<u64>(), solve(input));
+    }
+
+    #[test]
+    fn tall_straight_fall_counts_one_path() {
+        let input = format!("S\n{}", ".\n".repeat(200_000));
+        assert_eq!(solve_part2(&input), 1);
+        assert_eq!(solve(&input), 0);
+    }
 }
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::str::FromStr;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -23,6 +24,13 @@ impl Coordinate {
         let dz = (other.z - self.z) as i64;
         dx * dx + dy * dy + dz * dz
     }
+
+    pub fn manhattan_distance_from(&self, other: Coordinate) -> i64 {
+        let dx = other.x as i64 - self.x as i64;
+        let dy = other.y as i64 - self.y as i64;
+        let dz = other.z as i64 - self.z as i64;
+        dx.abs() + dy.abs() + dz.abs()
+    }
 }
 
 impl FromStr for Coordinate {
@@ -86,18 +94,21 @@ fn apply_connections(uf: &mut UnionFind, connections: &[(usize, usize)]) {
 }
 
 fn extract_and_sort_circuit_sizes(num_coordinates: usize, uf: &mut UnionFind) -> Vec<usize> {
-    let mut circuit_sizes = collect_unique_circuit_sizes(num_coordinates, uf);
+    let mut circuit_sizes = collect_circuit_sizes(num_coordinates, uf);
     sort_circuit_sizes_descending(&mut circuit_sizes);
     circuit_sizes
 }
 
-fn collect_unique_circuit_sizes(num_coordinates: usize, uf: &mut UnionFind) -> Vec<usize> {
-    let mut unique_sizes = std::collections::HashSet::new();
+/// One size per circuit: circuits of equal size are all kept, since the
+/// puzzle multiplies the sizes of the largest circuits, not of distinct sizes.
+fn collect_circuit_sizes(num_coordinates: usize, uf: &mut UnionFind) -> Vec<usize> {
+    let mut sizes = Vec::new();
     for i in 0..num_coordinates {
-        let size = uf.circuit_size(i);
-        unique_sizes.insert(size);
+        if uf.find(i) == i {
+            sizes.push(uf.circuit_size(i));
+        }
     }
-    unique_sizes.into_iter().collect()
+    sizes
 }
 
 fn sort_circuit_sizes_descending(sizes: &mut [usize]) {
@@ -196,6 +207,171 @@ fn multiply_x_coordinates_of_connection(
     }
 }
 
+/// Every pair of coordinates at most `max_distance` apart, as `(i, j)` with
+/// `i < j`, in ascending order. Only nearby coordinates are compared, through a
+/// [`SpatialIndex`] with cells as wide as `max_distance`.
+pub fn connections_within(coordinates: &[Coordinate], max_distance: f64) -> Vec<(usize, usize)> {
+    if max_distance.is_nan() || max_distance < 0.0 {
+        return Vec::new();
+    }
+    let cell_size = (max_distance.ceil() as i64).max(1);
+    let index = SpatialIndex::new(coordinates, cell_size);
+    let mut connections = Vec::new();
+
+    for (i, &coordinate) in coordinates.iter().enumerate() {
+        for j in index.neighbors_within(coordinate, max_distance) {
+            if j > i {
+                connections.push((i, j));
+            }
+        }
+    }
+
+    connections
+}
+
+/// Like [`connections_within`], with the Manhattan distance. Points that close
+/// are also within `max_distance` in a straight line, so the same
+/// [`SpatialIndex`] query finds them.
+pub fn connections_within_manhattan(
+    coordinates: &[Coordinate],
+    max_distance: i64,
+) -> Vec<(usize, usize)> {
+    if max_distance < 0 {
+        return Vec::new();
+    }
+    let index = SpatialIndex::new(coordinates, max_distance.max(1));
+    let mut connections = Vec::new();
+
+    for (i, &coordinate) in coordinates.iter().enumerate() {
+        for j in index.neighbors_within(coordinate, max_distance as f64) {
+            if j > i && coordinate.manhattan_distance_from(coordinates[j]) <= max_distance {
+                connections.push((i, j));
+            }
+        }
+    }
+
+    connections
+}
+
+/// Coordinates bucketed into cubic cells of a fixed side, so that a radius
+/// query only visits the cells the radius can reach.
+#[derive(Debug, Clone)]
+pub struct SpatialIndex {
+    cell_size: i64,
+    coordinates: Vec<Coordinate>,
+    cells: HashMap<(i64, i64, i64), Vec<usize>>,
+}
+
+impl SpatialIndex {
+    /// Indexes `coordinates` by position in the slice.
+    ///
+    /// # Panics
+    /// Panics if `cell_size` is not positive.
+    pub fn new(coordinates: &[Coordinate], cell_size: i64) -> Self {
+        assert!(cell_size > 0, "cell size must be positive, got {cell_size}");
+        let mut index = SpatialIndex {
+            cell_size,
+            coordinates: coordinates.to_vec(),
+            cells: HashMap::new(),
+        };
+        for (i, &coordinate) in coordinates.iter().enumerate() {
+            let cell = index.cell_of(coordinate);
+            index.cells.entry(cell).or_default().push(i);
+        }
+        index
+    }
+
+    /// The indices of the coordinates at most `radius` from `point`, in
+    /// ascending order. A point in the index is its own neighbor.
+    pub fn neighbors_within(&self, point: Coordinate, radius: f64) -> Vec<usize> {
+        if radius.is_nan() || radius < 0.0 {
+            return Vec::new();
+        }
+        let max_squared_distance = radius * radius;
+        let is_near = |&i: &usize| {
+            point.squared_distance_from(self.coordinates[i]) as f64 <= max_squared_distance
+        };
+
+        let reach = (radius / self.cell_size as f64).ceil() as i64;
+        let cells_in_reach = (2.0 * reach as f64 + 1.0).powi(3);
+        let mut neighbors: Vec<usize> = if cells_in_reach > self.cells.len() as f64 {
+            // Far-reaching queries are cheaper as a scan of the occupied cells
+            self.cells
+                .values()
+                .flatten()
+                .copied()
+                .filter(is_near)
+                .collect()
+        } else {
+            let (cx, cy, cz) = self.cell_of(point);
+            let mut neighbors = Vec::new();
+            for x in cx - reach..=cx + reach {
+                for y in cy - reach..=cy + reach {
+                    for z in cz - reach..=cz + reach {
+                        if let Some(cell) = self.cells.get(&(x, y, z)) {
+                            neighbors.extend(cell.iter().copied().filter(is_near));
+                        }
+                    }
+                }
+            }
+            neighbors
+        };
+
+        neighbors.sort_unstable();
+        neighbors
+    }
+
+    fn cell_of(&self, coordinate: Coordinate) -> (i64, i64, i64) {
+        (
+            (coordinate.x as i64).div_euclid(self.cell_size),
+            (coordinate.y as i64).div_euclid(self.cell_size),
+            (coordinate.z as i64).div_euclid(self.cell_size),
+        )
+    }
+}
+
+/// Connects every coordinate to its `k` nearest others, ties going to the
+/// lower index. Each undirected pair is listed once, as `(lower, higher)`, in
+/// ascending order.
+pub fn knn_connections(coordinates: &[Coordinate], k: usize) -> Vec<(usize, usize)> {
+    let mut connections = std::collections::BTreeSet::new();
+
+    for (i, coordinate) in coordinates.iter().enumerate() {
+        let mut neighbours: Vec<(i64, usize)> = coordinates
+            .iter()
+            .enumerate()
+            .filter(|&(j, _)| j != i)
+            .map(|(j, other)| (coordinate.squared_distance_from(*other), j))
+            .collect();
+        neighbours.sort_unstable();
+
+        for &(_, j) in neighbours.iter().take(k) {
+            connections.insert((i.min(j), i.max(j)));
+        }
+    }
+
+    connections.into_iter().collect()
+}
+
+/// Kruskal's algorithm: the shortest pairs that join two separate circuits,
+/// until every coordinate is connected with the least total distance.
+pub fn minimum_spanning_tree(coordinates: &[Coordinate]) -> Vec<(usize, usize, f64)> {
+    let mut uf = UnionFind::new(coordinates.len());
+    let mut edges = Vec::with_capacity(coordinates.len().saturating_sub(1));
+
+    for (i, j, distance) in get_sorted_pair_distances(coordinates) {
+        if edges.len() + 1 >= coordinates.len() {
+            break;
+        }
+        if uf.find(i) != uf.find(j) {
+            uf.union(i, j);
+            edges.push((i, j, distance));
+        }
+    }
+
+    edges
+}
+
 #[derive(Debug, Clone)]
 pub struct UnionFind {
     parent: Vec<usize>,
@@ -211,10 +387,20 @@ impl UnionFind {
     }
 
     pub fn find(&mut self, x: usize) -> usize {
-        if self.parent[x] != x {
-            self.parent[x] = self.find(self.parent[x]); // Path compression
+        let mut root = x;
+        while self.parent[root] != root {
+            root = self.parent[root];
+        }
+
+        // Path compression, without recursing down a long chain
+        let mut current = x;
+        while self.parent[current] != root {
+            let next = self.parent[current];
+            self.parent[current] = root;
+            current = next;
         }
-        self.parent[x]
+
+        root
     }
 
     pub fn union(&mut self, x: usize, y: usize) {
@@ -241,6 +427,16 @@ impl UnionFind {
         let root = self.find(x);
         self.size[root]
     }
+
+    pub fn connected(&mut self, a: usize, b: usize) -> bool {
+        self.find(a) == self.find(b)
+    }
+
+    pub fn num_components(&mut self) -> usize {
+        (0..self.parent.len())
+            .filter(|&x| self.find(x) == x)
+            .count()
+    }
 }
 
 #[cfg(test)]
@@ -279,6 +475,18 @@ mod tests {
         assert_eq!(dist, 13.0);
     }
 
+    #[test]
+    fn test_coordinate_manhattan_distance_from() {
+        let coord1 = Coordinate::new(0, 0, 0);
+        let coord2 = Coordinate::new(3, 4, 12);
+        assert_eq!(coord1.manhattan_distance_from(coord2), 19);
+        assert_eq!(coord2.manhattan_distance_from(coord1), 19);
+        assert_eq!(
+            Coordinate::new(-5, 2, 7).manhattan_distance_from(Coordinate::new(1, -2, 7)),
+            10
+        );
+    }
+
     #[test]
     fn test_get_all_circuit_sizes() {
         let coords = vec![
@@ -322,6 +530,39 @@ mod tests {
         // This could create circuits of [4, 1] or [3, 2]
     }
 
+    #[test]
+    fn test_equal_sized_circuits_are_all_counted() {
+        // Three separate pairs: circuits of sizes 2, 2 and 2
+        let input = "0,0,0
+1,0,0
+100,0,0
+101,0,0
+200,0,0
+201,0,0";
+        let coordinates = parse_coordinates(input).unwrap();
+        let connections = vec![(0, 1), (2, 3), (4, 5)];
+        assert_eq!(
+            get_all_circuit_sizes(&coordinates, &connections),
+            vec![2, 2, 2]
+        );
+        assert_eq!(solve_playground_problem(input, 3), 8);
+    }
+
+    #[test]
+    fn test_known_example_gives_40() {
+        let input = "162,817,812\n57,618,57\n906,360,560\n592,479,940\n352,342,300\n\
+                     466,668,158\n542,29,236\n431,825,988\n739,650,466\n52,470,668\n\
+                     216,146,977\n819,987,18\n117,168,530\n805,96,715\n346,949,466\n\
+                     970,615,88\n941,993,340\n862,61,35\n984,92,344\n425,690,689";
+        // Ten connections leave circuits of 5, 4, 2, 2 and singles
+        let coordinates = parse_coordinates(input).unwrap();
+        let sorted_pairs = sort_pairs_by_distance(calculate_all_pair_distances(&coordinates));
+        let connections = select_closest_unconnected_pairs(&coordinates, &sorted_pairs, 10);
+        let sizes = get_all_circuit_sizes(&coordinates, &connections);
+        assert_eq!(&sizes[..4], &[5, 4, 2, 2]);
+        assert_eq!(solve_playground_problem(input, 10), 40);
+    }
+
     #[test]
     fn test_union_find_initialization() {
         let mut uf = UnionFind::new(5);
@@ -368,6 +609,47 @@ mod tests {
         assert_eq!(uf.circuit_size(3), 1);
     }
 
+    #[test]
+    fn test_union_find_long_chain() {
+        let mut uf = UnionFind::new(1000);
+        for i in 0..999 {
+            uf.union(i, i + 1);
+        }
+
+        let root = uf.find(999);
+        assert!((0..1000).all(|i| uf.find(i) == root));
+        assert_eq!(uf.circuit_size(0), 1000);
+        assert_eq!(uf.num_components(), 1);
+    }
+
+    #[test]
+    fn test_union_find_degenerate_chain_is_compressed() {
+        // A chain no sequence of unions by size would build: i's parent is i + 1
+        let n = 1_000_000;
+        let mut uf = UnionFind::new(n);
+        for i in 0..n - 1 {
+            uf.parent[i] = i + 1;
+        }
+
+        assert_eq!(uf.find(0), n - 1);
+        assert!((0..n).all(|i| uf.parent[i] == n - 1));
+    }
+
+    #[test]
+    fn test_union_find_num_components_and_connected() {
+        let mut uf = UnionFind::new(5);
+        assert_eq!(uf.num_components(), 5);
+
+        uf.union(0, 1);
+        uf.union(2, 3);
+
+        assert_eq!(uf.num_components(), 3);
+        assert!(uf.connected(0, 1));
+        assert!(uf.connected(3, 2));
+        assert!(!uf.connected(0, 2));
+        assert!(uf.connected(4, 4));
+    }
+
     #[test]
     fn test_parse_coordinates() {
         let input = "162,817,812\n57,618,57\n906,360,560";
@@ -413,4 +695,189 @@ mod tests {
         let pair_02 = pairs.iter().find(|(i, j, _)| (*i, *j) == (0, 2)).unwrap();
         assert_eq!(pair_02.2, 12.0);
     }
+
+    #[test]
+    fn test_minimum_spanning_tree() {
+        // A 3-4-5 right triangle plus a far point closest to (3, 4, 0)
+        let coords = vec![
+            Coordinate::new(0, 0, 0),
+            Coordinate::new(3, 0, 0),
+            Coordinate::new(3, 4, 0),
+            Coordinate::new(3, 4, 12),
+        ];
+
+        let mst = minimum_spanning_tree(&coords);
+
+        assert_eq!(mst, vec![(0, 1, 3.0), (1, 2, 4.0), (2, 3, 12.0)]);
+        let total: f64 = mst.iter().map(|(_, _, distance)| distance).sum();
+        assert_eq!(total, 19.0);
+    }
+
+    #[test]
+    fn test_minimum_spanning_tree_has_n_minus_one_edges() {
+        let input = "162,817,812\n57,618,57\n906,360,560\n592,479,940\n352,342,300\n\
+                     466,668,158\n542,29,236\n431,825,988\n739,650,466\n52,470,668";
+        let coordinates = parse_coordinates(input).unwrap();
+
+        let mst = minimum_spanning_tree(&coordinates);
+
+        assert_eq!(mst.len(), coordinates.len() - 1);
+        let connections: Vec<_> = mst.iter().map(|&(i, j, _)| (i, j)).collect();
+        assert_eq!(
+            get_all_circuit_sizes(&coordinates, &connections),
+            vec![coordinates.len()]
+        );
+        assert!(minimum_spanning_tree(&[]).is_empty());
+        assert!(minimum_spanning_tree(&coordinates[..1]).is_empty());
+    }
+
+    #[test]
+    fn test_knn_connections_form_a_chain() {
+        // Gaps of 1, 2, 3 and 4 along the x axis: each point's nearest
+        // neighbour is the one before it, so the 1-NN pairs chain them all
+        let coords = vec![
+            Coordinate::new(0, 0, 0),
+            Coordinate::new(1, 0, 0),
+            Coordinate::new(3, 0, 0),
+            Coordinate::new(6, 0, 0),
+            Coordinate::new(10, 0, 0),
+        ];
+
+        let connections = knn_connections(&coords, 1);
+
+        assert_eq!(connections, vec![(0, 1), (1, 2), (2, 3), (3, 4)]);
+        assert!(knn_connections(&coords, 0).is_empty());
+        assert_eq!(knn_connections(&coords, 10).len(), 10);
+    }
+
+    #[test]
+    fn test_knn_connections_break_ties_by_index() {
+        // The middle point is as close to both ends, and picks the first
+        let coords = vec![
+            Coordinate::new(0, 0, 0),
+            Coordinate::new(1, 0, 0),
+            Coordinate::new(2, 0, 0),
+            Coordinate::new(1, 5, 0),
+        ];
+
+        let connections = knn_connections(&coords, 1);
+
+        assert_eq!(connections, vec![(0, 1), (1, 2), (1, 3)]);
+    }
+
+    #[test]
+    fn test_connections_within_separates_clusters() {
+        let coords = vec![
+            Coordinate::new(0, 0, 0),
+            Coordinate::new(3, 4, 0),
+            Coordinate::new(0, 5, 0),
+            Coordinate::new(100, 100, 100),
+            Coordinate::new(100, 103, 104),
+        ];
+
+        // Within each cluster points are at most 5 apart, clusters ~170 apart
+        let connections = connections_within(&coords, 5.0);
+
+        assert_eq!(connections, vec![(0, 1), (0, 2), (1, 2), (3, 4)]);
+        let circuit_sizes = get_all_circuit_sizes(&coords, &connections);
+        assert_eq!(circuit_sizes, vec![3, 2]);
+        assert_eq!(connections_within(&coords, 4.9), vec![(1, 2)]);
+        assert!(connections_within(&coords, -1.0).is_empty());
+    }
+
+    fn pseudo_random(seed: &mut u64) -> u64 {
+        *seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        *seed >> 33
+    }
+
+    /// Compares every pair, as `connections_within` did before the index.
+    fn brute_force_connections_within(
+        coordinates: &[Coordinate],
+        max_distance: f64,
+    ) -> Vec<(usize, usize)> {
+        calculate_all_pair_distances(coordinates)
+            .into_iter()
+            .filter(|&(_, _, distance)| distance <= max_distance)
+            .map(|(i, j, _)| (i, j))
+            .collect()
+    }
+
+    #[test]
+    fn test_spatial_index_matches_brute_force() {
+        let mut seed = 95;
+        let coords: Vec<Coordinate> = (0..200)
+            .map(|_| {
+                let mut axis = || (pseudo_random(&mut seed) % 200) as i32 - 100;
+                Coordinate::new(axis(), axis(), axis())
+            })
+            .collect();
+
+        for max_distance in [0.0, 7.5, 20.0, 45.0, 400.0, f64::INFINITY] {
+            assert_eq!(
+                connections_within(&coords, max_distance),
+                brute_force_connections_within(&coords, max_distance),
+                "max_distance {max_distance}"
+            );
+        }
+
+        let index = SpatialIndex::new(&coords, 10);
+        let point = Coordinate::new(3, -8, 15);
+        let expected: Vec<usize> = (0..coords.len())
+            .filter(|&i| coords[i].distance_from(point) <= 25.0)
+            .collect();
+        assert_eq!(index.neighbors_within(point, 25.0), expected);
+    }
+
+    #[test]
+    fn manhattan_distance_spans_the_whole_i32_range() {
+        let low = Coordinate::new(i32::MIN, i32::MIN, i32::MIN);
+        let high = Coordinate::new(i32::MAX, i32::MAX, i32::MAX);
+        let expected = 3 * (u32::MAX as i64);
+        assert_eq!(low.manhattan_distance_from(high), expected);
+        assert_eq!(high.manhattan_distance_from(low), expected);
+    }
+
+    #[test]
+    fn test_connections_within_manhattan() {
+        let coords = vec![
+            Coordinate::new(0, 0, 0),
+            Coordinate::new(3, 4, 0),
+            Coordinate::new(0, 0, 6),
+            Coordinate::new(100, 100, 100),
+            Coordinate::new(100, 103, 104),
+        ];
+
+        // (0, 1) is 5 apart in a straight line but 7 in Manhattan distance
+        assert_eq!(
+            connections_within(&coords, 6.0),
+            vec![(0, 1), (0, 2), (3, 4)]
+        );
+        assert_eq!(connections_within_manhattan(&coords, 6), vec![(0, 2)]);
+        assert_eq!(
+            connections_within_manhattan(&coords, 7),
+            vec![(0, 1), (0, 2), (3, 4)]
+        );
+        assert!(connections_within_manhattan(&coords, -1).is_empty());
+
+        let mut seed = 99;
+        let coords: Vec<Coordinate> = (0..150)
+            .map(|_| {
+                let mut axis = || (pseudo_random(&mut seed) % 100) as i32 - 50;
+                Coordinate::new(axis(), axis(), axis())
+            })
+            .collect();
+        for max_distance in [0, 10, 30, 200] {
+            let expected: Vec<(usize, usize)> = calculate_all_pair_distances(&coords)
+                .into_iter()
+                .map(|(i, j, _)| (i, j))
+                .filter(|&(i, j)| coords[i].manhattan_distance_from(coords[j]) <= max_distance)
+                .collect();
+            assert_eq!(
+                connections_within_manhattan(&coords, max_distance),
+                expected
+            );
+        }
+    }
 }